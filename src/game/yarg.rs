@@ -2,15 +2,18 @@ use crate::play::PlayTrait;
 use crate::util::timestamp::NsTimestamp;
 use crate::{game::yarg, util::uuid::UuidString};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Instrument {
     Guitar,
     Drums,
 }
 
-#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Difficulties are ordered from the easiest to the hardest one.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum Difficulty {
     Beginner,
@@ -21,6 +24,94 @@ pub enum Difficulty {
     ExpertPlus,
 }
 
+#[derive(Debug)]
+pub struct UnknownInstrument;
+
+impl fmt::Display for UnknownInstrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown instrument name")
+    }
+}
+
+impl std::error::Error for UnknownInstrument {}
+
+#[derive(Debug)]
+pub struct UnknownDifficulty;
+
+impl fmt::Display for UnknownDifficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown difficulty name")
+    }
+}
+
+impl std::error::Error for UnknownDifficulty {}
+
+impl Instrument {
+    pub const ALL: [Instrument; 2] = [Instrument::Guitar, Instrument::Drums];
+
+    /// Get the name of the instrument, as used in serialized data.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Instrument::Guitar => "guitar",
+            Instrument::Drums => "drums",
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = UnknownInstrument;
+
+    /// Parse the name of an instrument, as used in serialized data.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|instrument| instrument.as_str() == s).ok_or(UnknownInstrument)
+    }
+}
+
+impl Difficulty {
+    /// All difficulties, from the easiest to the hardest one.
+    pub const ALL: [Difficulty; 6] = [
+        Difficulty::Beginner,
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+        Difficulty::ExpertPlus,
+    ];
+
+    /// Get the name of the difficulty, as used in serialized data.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "beginner",
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+            Difficulty::ExpertPlus => "expert_plus",
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = UnknownDifficulty;
+
+    /// Parse the name of a difficulty, as used in serialized data.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|difficulty| difficulty.as_str() == s).ok_or(UnknownDifficulty)
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Mode {
@@ -69,4 +160,16 @@ impl PlayTrait for yarg::Play {
     fn comment(&self) -> String {
         self.comment.clone()
     }
+    fn score(&self) -> u64 {
+        self.score
+    }
+    fn song_id(&self) -> String {
+        self.song_id.clone()
+    }
+    fn instrument(&self) -> String {
+        self.instrument.as_str().to_string()
+    }
+    fn difficulty(&self) -> String {
+        self.difficulty.as_str().to_string()
+    }
 }
@@ -1,5 +1,19 @@
+use crate::library::cache::{HashingMethod, LibraryCache, compute_hash_of_file, file_metadata};
+use crate::library::database::{self, LibraryDatabase, LibraryEntry, QualityState};
+use crate::library::index::{LibraryIndex, ScanError};
+use crate::util::{file_ex, lockfile};
 use crate::util::uuid::UuidString;
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Instant;
 use std::{fmt, path::PathBuf, thread::sleep, time::Duration};
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -10,6 +24,85 @@ pub enum ProcessingType {
     CompressShredVideo,
 }
 
+impl ProcessingType {
+    /// The quality state of a file produced by this type of processing.
+    pub fn target_quality(self) -> QualityState {
+        match self {
+            ProcessingType::CompressImportantVideo => QualityState::Compressed,
+            ProcessingType::CompressCrumpleVideo => QualityState::Crumpled,
+            ProcessingType::CompressShredVideo => QualityState::Shredded,
+        }
+    }
+
+    /// Arguments passed to ffmpeg to encode the output file, see [`Job::ProcessVideo`].
+    ///
+    /// - [`ProcessingType::CompressImportantVideo`] "folds" the video - it is re-encoded in a visually lossless way, keeping the resolution.
+    /// - [`ProcessingType::CompressCrumpleVideo`] scales the video down to 720p, targetting around 10 MiB per 2.5 minutes of video.
+    /// - [`ProcessingType::CompressShredVideo`] scales the video down to 360p at a very low bitrate.
+    pub fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            ProcessingType::CompressImportantVideo => &["-c:v", "libx264", "-preset", "slow", "-crf", "18", "-c:a", "copy"],
+            ProcessingType::CompressCrumpleVideo => &[
+                "-vf", "scale=-2:720", "-c:v", "libx264", "-preset", "slow", "-b:v", "480k", "-maxrate", "480k", "-bufsize", "960k", "-c:a",
+                "aac", "-b:a", "64k",
+            ],
+            ProcessingType::CompressShredVideo => &[
+                "-vf", "scale=-2:360", "-c:v", "libx264", "-preset", "slow", "-b:v", "200k", "-maxrate", "200k", "-bufsize", "400k", "-c:a",
+                "aac", "-b:a", "48k",
+            ],
+        }
+    }
+}
+
+/// Part of a video to cut out, in milliseconds since the start of the source video.
+///
+/// Either end of the range can be left open - a missing start point means "from the beginning of the video",
+/// and a missing end point means "until the end of the video".
+///
+/// The fields can also be deserialized from their old names, `cut_point_start_ms` and `cut_point_end_ms`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CutRange {
+    #[serde(alias = "cut_point_start_ms")]
+    pub start_ms: Option<u64>,
+    #[serde(alias = "cut_point_end_ms")]
+    pub end_ms: Option<u64>,
+}
+
+impl CutRange {
+    /// Create a new validated cut range.
+    ///
+    /// # Errors
+    /// This function returns [`Error::InvalidCutRange`] if both points are given and the end point is not after the start point.
+    pub fn new(start_ms: Option<u64>, end_ms: Option<u64>) -> Result<Self, Error> {
+        let range = Self { start_ms, end_ms };
+        range.validate()?;
+        Ok(range)
+    }
+
+    /// Check that the end point is after the start point, if both of them are given.
+    pub fn validate(&self) -> Result<(), Error> {
+        match (self.start_ms, self.end_ms) {
+            (Some(start_ms), Some(end_ms)) if end_ms <= start_ms => Err(Error::InvalidCutRange { start_ms, end_ms }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Arguments limiting ffmpeg to this range of the input file. They have to be passed before the input file.
+    ///
+    /// A missing start point cuts from the beginning of the file, a missing end point cuts until the end of the file.
+    pub fn ffmpeg_input_args(&self) -> Vec<String> {
+        let seconds = |ms: u64| format!("{}.{:03}", ms / 1000, ms % 1000);
+        let mut args = Vec::new();
+        if let Some(start_ms) = self.start_ms {
+            args.extend(["-ss".to_string(), seconds(start_ms)]);
+        }
+        if let Some(end_ms) = self.end_ms {
+            args.extend(["-to".to_string(), seconds(end_ms)]);
+        }
+        args
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "details")]
 #[serde(rename_all = "snake_case")]
@@ -24,45 +117,596 @@ pub enum Job {
         message: String,
         time_nanos: i128,
     },
+    /// Cut a range out of a video without re-encoding it, see [`Success::Cut`].
+    ///
+    /// The streams are copied, so the cut points snap to the nearest keyframes of the source video.
     CutVideo {
         source_proof_uuid: UuidString,
         source_path: PathBuf,
-        cut_point_start_ms: Option<u64>,
-        cut_point_end_ms: Option<u64>,
+        #[serde(flatten)]
+        cut_range: CutRange,
         destination_path: PathBuf,
     },
+    /// Transcode a video into a more destructive [`QualityState`], see [`Success::ProcessedVideo`].
+    ///
+    /// The job is refused with [`Error::InvalidQualityTransition`] if `source_quality` is not less destructive than the target quality.
     ProcessVideo {
         source_proof_uuid: UuidString,
         source_path: PathBuf,
+        /// Quality of the source file, copied from its library entry when the job is created.
+        source_quality: QualityState,
         processing_type: ProcessingType,
         destination_path: PathBuf,
     },
+    /// Compute the SHA256 hash of a file, see [`Success::Hash`].
+    ComputeHash {
+        path: PathBuf,
+    },
+    /// Rescan the library directory, updating its library database and saving a new index, see [`Success::Scanned`].
+    ScanLibrary {
+        library_dir: PathBuf,
+    },
+    /// Extract a single frame of a video into an image, see [`Success::Thumbnail`].
+    ///
+    /// The image format is chosen by ffmpeg based on the extension of `destination_path`, for example `.jpg` or `.png`.
+    GenerateThumbnail {
+        source_proof_uuid: UuidString,
+        source_path: PathBuf,
+        timestamp_ms: u64,
+        destination_path: PathBuf,
+    },
 }
 
 #[derive(Debug)]
-pub struct Error {}
+pub enum Error {
+    /// The job would turn a file into a file of the same or better quality, which is not possible with lossy processing.
+    InvalidQualityTransition { from: QualityState, to: QualityState },
+
+    /// The end point of a cut is not after its start point.
+    InvalidCutRange { start_ms: u64, end_ms: u64 },
+
+    /// The job was cancelled before it finished, see [`Job::run_cancellable`].
+    Cancelled,
+
+    /// The job did not finish before its deadline, see [`Job::run_with_deadline`].
+    TimedOut,
+
+    /// The ffmpeg executable could not be started.
+    CannotRunFfmpeg(io::Error),
+
+    /// ffmpeg exited with an error. Contains the exit code (if there is one) and the error output.
+    FfmpegFailed { status: Option<i32>, stderr: String },
+
+    /// The file to hash could not be read, see [`Job::ComputeHash`].
+    CannotHashFile(file_ex::Error),
+
+    /// The library database could not be opened or saved, for example because another process has it locked.
+    CannotAccessDatabase(lockfile::Error),
+
+    /// The library directory could not be scanned, see [`LibraryIndex::scan_library_dir`].
+    CannotScanLibrary(ScanError),
+
+    /// The new library index could not be saved.
+    CannotSaveIndex(file_ex::Error),
+
+    /// The job panicked while running, see [`catch_job_panic`]. Contains the panic message.
+    JobPanicked(String),
+
+    /// ffmpeg finished, but did not write any frame to the thumbnail file, see [`Job::GenerateThumbnail`].
+    NoFrameExtracted { timestamp_ms: u64 },
+}
+
+/// Result of a successful job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Success {
+    /// The job finished, and does not have any results.
+    Finished,
+
+    /// A video was processed into a new file, see [`Job::ProcessVideo`].
+    ///
+    /// The new file is not in the library database yet - see [`Success::record_in_library`].
+    ProcessedVideo {
+        /// UUID of the source library entry.
+        dry: UuidString,
+        /// UUID for the library entry of the processed file.
+        wet: UuidString,
+        destination_path: PathBuf,
+        quality: QualityState,
+    },
+
+    /// The hash of a file was computed, see [`Job::ComputeHash`].
+    ///
+    /// The hash is not in the library cache yet - see [`Success::record_in_cache`].
+    Hash { path: PathBuf, sha256: String },
+
+    /// The library directory was scanned, see [`Job::ScanLibrary`].
+    Scanned {
+        /// Number of files recorded in the new index.
+        files_scanned: usize,
+        /// Number of files found in the library directory which were not recorded in the index.
+        skipped: usize,
+        duration_nanos: u64,
+    },
+
+    /// A range of a video proof was cut into a new file, see [`Job::CutVideo`].
+    ///
+    /// The cut file is not added to the library database - it is picked up by the next library scan.
+    Cut { proof: UuidString, path: PathBuf },
+
+    /// A thumbnail of a video proof was generated, see [`Job::GenerateThumbnail`].
+    Thumbnail { proof: UuidString, path: PathBuf },
+}
+
+/// Progress of a running job, see [`Job::run`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobProgress {
+    /// How much of the job is done, from 0 to 100.
+    pub percent: f64,
+
+    /// Short description of what the job is doing right now.
+    pub stage: String,
+}
+
+impl JobProgress {
+    pub fn new(percent: f64, stage: impl Into<String>) -> Self {
+        Self {
+            percent: percent.clamp(0.0, 100.0),
+            stage: stage.into(),
+        }
+    }
+}
+
+impl Success {
+    /// Add the files produced by the job to the library database.
+    ///
+    /// For [`Success::ProcessedVideo`], the processed file is hashed and added as a new entry with the UUID `wet`,
+    /// pointing to the `dry` entry as its source. Other results don't produce any files, so nothing happens for them.
+    ///
+    /// Returns the UUID of the added entry, if there is one.
+    pub fn record_in_library(&self, library_dir: &Path, database: &mut LibraryDatabase) -> Result<Option<uuid::Uuid>, database::Error> {
+        match self {
+            Success::Finished | Success::Hash { .. } | Success::Scanned { .. } | Success::Cut { .. } | Success::Thumbnail { .. } => Ok(None),
+            Success::ProcessedVideo {
+                dry,
+                wet,
+                destination_path,
+                quality,
+            } => {
+                let sha256 = compute_hash_of_file(destination_path, HashingMethod::SHA256)?;
+                let library_entry = LibraryEntry {
+                    uuid: *wet,
+                    sha256,
+                    quality: *quality,
+                    dry: Some(*dry),
+                    ..Default::default()
+                };
+                Ok(Some(database.add_entry(library_dir, destination_path, library_entry)?))
+            }
+        }
+    }
+
+    /// Record a computed hash in the library cache, so that the file does not have to be hashed again during a scan.
+    ///
+    /// Only [`Success::Hash`] results are recorded, and only if the cache uses [`HashingMethod::SHA256`].
+    /// The cache is not saved to disk automatically - use [`LibraryCache::write_to_file`] afterwards.
+    ///
+    /// Returns whether the hash was recorded.
+    ///
+    /// # Errors
+    /// This function returns [`file_ex::Error::CannotReadFile`] if the metadata of the file could not be read.
+    pub fn record_in_cache(&self, cache: &mut LibraryCache) -> file_ex::Result<bool> {
+        let Success::Hash { path, sha256 } = self else {
+            return Ok(false);
+        };
+        if cache.hashing_method() != HashingMethod::SHA256 {
+            return Ok(false);
+        }
+        let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let (file_size, birth_timestamp, modify_timestamp) = file_metadata(path).map_err(file_ex::Error::CannotReadFile)?;
+        cache.insert(filename, file_size, birth_timestamp, modify_timestamp, sha256.clone());
+        Ok(true)
+    }
+}
 
 impl fmt::Display for Error {
-    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        todo!();
-        // write!(f, "unknown error while running a job") // todo
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidQualityTransition { from, to } => {
+                write!(f, "cannot process a file of quality {from:?} into a file of quality {to:?}")
+            }
+            Error::InvalidCutRange { start_ms, end_ms } => {
+                write!(f, "cut end point ({end_ms} ms) is not after the start point ({start_ms} ms)")
+            }
+            Error::Cancelled => write!(f, "job was cancelled"),
+            Error::TimedOut => write!(f, "job did not finish before its deadline"),
+            Error::CannotRunFfmpeg(e) => write!(f, "could not run ffmpeg: {e}"),
+            Error::FfmpegFailed { status, stderr } => match status {
+                Some(status) => write!(f, "ffmpeg exited with status {status}: {stderr}"),
+                None => write!(f, "ffmpeg was terminated by a signal: {stderr}"),
+            },
+            Error::CannotHashFile(e) => write!(f, "could not hash file: {e:?}"),
+            Error::CannotAccessDatabase(e) => write!(f, "could not access the library database: {e:?}"),
+            Error::CannotScanLibrary(e) => write!(f, "could not scan the library: {e:?}"),
+            Error::CannotSaveIndex(e) => write!(f, "could not save the library index: {e:?}"),
+            Error::JobPanicked(message) => write!(f, "job panicked: {message}"),
+            Error::NoFrameExtracted { timestamp_ms } => write!(f, "ffmpeg did not extract any frame at {timestamp_ms} ms"),
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
 impl Job {
-    pub fn run(&self) -> Result<(), Error> {
+    /// Create a [`Job::ProcessVideo`] job for the file described by the `source` library entry.
+    ///
+    /// The quality of the entry is recorded as the job's `source_quality`.
+    ///
+    /// # Errors
+    /// This function returns [`Error::InvalidQualityTransition`] if the job would "upgrade" the quality of the file, see [`Self::check_quality_transition`].
+    pub fn process_video(source: &LibraryEntry, source_path: PathBuf, processing_type: ProcessingType, destination_path: PathBuf) -> Result<Self, Error> {
+        let job = Job::ProcessVideo {
+            source_proof_uuid: source.uuid,
+            source_path,
+            source_quality: source.quality,
+            processing_type,
+            destination_path,
+        };
+        job.check_quality_transition()?;
+        Ok(job)
+    }
+
+    /// Check if the job would "upgrade" the quality of its source file.
+    ///
+    /// [`Job::ProcessVideo`] jobs are refused if their target quality is not further down the ladder than their `source_quality`,
+    /// see [`QualityState::can_transition_to`]. Other jobs are always accepted.
+    /// This check is also done when the job is ran, before processing anything.
+    pub fn check_quality_transition(&self) -> Result<(), Error> {
+        if let Job::ProcessVideo {
+            source_quality,
+            processing_type,
+            ..
+        } = self
+        {
+            let to = processing_type.target_quality();
+            if !source_quality.can_transition_to(to) {
+                return Err(Error::InvalidQualityTransition { from: *source_quality, to });
+            }
+        }
+        Ok(())
+    }
+
+    /// How often long-running jobs check whether they have been cancelled.
+    const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Run the job, reporting its progress to `on_progress` if it is given.
+    ///
+    /// Every job reports 0% when it starts and 100% once it finishes successfully.
+    /// Jobs using ffmpeg also report the progress of the encoding in between.
+    pub fn run(&self, on_progress: Option<&mut dyn FnMut(JobProgress)>) -> Result<Success, Error> {
+        self.run_cancellable(&AtomicBool::new(false), on_progress)
+    }
+
+    /// Run the job, stopping early with [`Error::Cancelled`] once `cancelled` is set.
+    ///
+    /// Only long-running jobs check the flag, every [`Self::CANCELLATION_POLL_INTERVAL`] - short jobs always run to completion.
+    /// A panic inside of the job does not propagate to the caller - it is returned as [`Error::JobPanicked`] instead.
+    pub fn run_cancellable(&self, cancelled: &AtomicBool, on_progress: Option<&mut dyn FnMut(JobProgress)>) -> Result<Success, Error> {
+        let on_progress = match on_progress {
+            Some(on_progress) => on_progress,
+            None => &mut |_| {},
+        };
+        on_progress(JobProgress::new(0.0, "starting"));
+        let success = catch_job_panic(|| self.run_inner(cancelled, on_progress))?;
+        on_progress(JobProgress::new(100.0, "finished"));
+        Ok(success)
+    }
+
+    fn run_inner(&self, cancelled: &AtomicBool, on_progress: &mut dyn FnMut(JobProgress)) -> Result<Success, Error> {
         match self {
             Job::DisplayMessage { message } => println!("{}", message),
-            Job::Sleep { time_nanos } => sleep(Duration::from_nanos(*time_nanos as u64)),
+            Job::Sleep { time_nanos } => Self::sleep_cancellable(Duration::from_nanos(*time_nanos as u64), cancelled)?,
             Job::DisplayMessageAndSleep { message, time_nanos } => {
                 println!("{}", message);
-                sleep(Duration::from_nanos(*time_nanos as u64));
+                Self::sleep_cancellable(Duration::from_nanos(*time_nanos as u64), cancelled)?;
+            }
+            Job::CutVideo {
+                source_proof_uuid,
+                source_path,
+                cut_range,
+                destination_path,
+            } => {
+                cut_range.validate()?;
+                let input_args = cut_range.ffmpeg_input_args();
+                let input_args: Vec<&str> = input_args.iter().map(String::as_str).collect();
+                Self::run_ffmpeg(&input_args, source_path, &["-c", "copy"], destination_path, on_progress)?;
+                return Ok(Success::Cut {
+                    proof: *source_proof_uuid,
+                    path: destination_path.clone(),
+                });
+            }
+            Job::ProcessVideo {
+                source_proof_uuid,
+                source_path,
+                processing_type,
+                destination_path,
+                ..
+            } => {
+                self.check_quality_transition()?;
+                Self::run_ffmpeg(&[], source_path, processing_type.ffmpeg_args(), destination_path, on_progress)?;
+                return Ok(Success::ProcessedVideo {
+                    dry: *source_proof_uuid,
+                    wet: UuidString::new_v4(),
+                    destination_path: destination_path.clone(),
+                    quality: processing_type.target_quality(),
+                });
+            }
+            Job::ComputeHash { path } => {
+                let sha256 = compute_hash_of_file(path, HashingMethod::SHA256).map_err(Error::CannotHashFile)?;
+                return Ok(Success::Hash { path: path.clone(), sha256 });
+            }
+            Job::ScanLibrary { library_dir } => return Self::scan_library(library_dir, on_progress),
+            Job::GenerateThumbnail {
+                source_proof_uuid,
+                source_path,
+                timestamp_ms,
+                destination_path,
+            } => {
+                Self::generate_thumbnail(source_path, *timestamp_ms, destination_path, on_progress)?;
+                return Ok(Success::Thumbnail {
+                    proof: *source_proof_uuid,
+                    path: destination_path.clone(),
+                });
             }
-            Job::CutVideo { .. } => todo!(),
-            Job::ProcessVideo { .. } => todo!(),
         }
-        Ok(())
+        Ok(Success::Finished)
+    }
+
+    /// Scan the library directory, and save the updated library database and the new index inside of it.
+    ///
+    /// The library database stays locked for the whole scan, so other processes cannot modify it in the meantime.
+    fn scan_library(library_dir: &Path, on_progress: &mut dyn FnMut(JobProgress)) -> Result<Success, Error> {
+        let start = Instant::now();
+        let mut database =
+            LibraryDatabase::read_or_create_new_safe(library_dir.join(LibraryDatabase::STANDARD_FILENAME)).map_err(Error::CannotAccessDatabase)?;
+
+        let mut total = 0;
+        let index = LibraryIndex::scan_library_dir_with_progress(library_dir, &mut database, |progress| {
+            total = progress.total;
+            on_progress(JobProgress::new(progress.index as f64 / progress.total as f64 * 100.0, "scanning"));
+        })
+        .map_err(Error::CannotScanLibrary)?;
+
+        database.write_to_file().map_err(Error::CannotAccessDatabase)?;
+        index.save(&library_dir.join(LibraryIndex::STANDARD_FILENAME)).map_err(Error::CannotSaveIndex)?;
+
+        // every file which was not skipped ends up in the index under its own path
+        let files_scanned = index.files.len();
+        Ok(Success::Scanned {
+            files_scanned,
+            skipped: total - files_scanned,
+            duration_nanos: start.elapsed().as_nanos() as u64,
+        })
+    }
+
+    /// How far before the end of a video the last frame is looked for, when a thumbnail is requested past the end of the video.
+    const THUMBNAIL_END_MARGIN: Duration = Duration::from_millis(100);
+
+    /// Extract the frame at `timestamp_ms` into an image file.
+    ///
+    /// If the timestamp is past the end of the video, a frame from the very end of the video is extracted instead.
+    /// This only works if the duration of the video can be determined, see [`probe_duration`].
+    fn generate_thumbnail(source_path: &Path, timestamp_ms: u64, destination_path: &Path, on_progress: &mut dyn FnMut(JobProgress)) -> Result<(), Error> {
+        let mut timestamp = Duration::from_millis(timestamp_ms);
+        if let Some(duration) = probe_duration(source_path)
+            && timestamp >= duration
+        {
+            timestamp = duration.saturating_sub(Self::THUMBNAIL_END_MARGIN);
+        }
+
+        // a stale thumbnail must not be mistaken for a freshly generated one
+        let _ = fs::remove_file(destination_path);
+        let seek_position = format!("{:.3}", timestamp.as_secs_f64());
+        Self::run_ffmpeg(&["-ss", &seek_position], source_path, &["-frames:v", "1", "-an"], destination_path, on_progress)?;
+
+        // ffmpeg exits successfully without writing anything if there are no frames after the seek position
+        match fs::metadata(destination_path) {
+            Ok(metadata) if metadata.len() > 0 => Ok(()),
+            _ => Err(Error::NoFrameExtracted { timestamp_ms }),
+        }
+    }
+
+    /// Run ffmpeg on the input file, writing to the output file (overwriting it if it exists).
+    ///
+    /// The encoding progress is read from ffmpeg's `-progress` output. It can only be reported if the duration of the input file
+    /// is known, see [`probe_duration`].
+    ///
+    /// `input_args` are passed before the input file (for example to seek in it), `args` are passed between the input and the output file.
+    fn run_ffmpeg(
+        input_args: &[&str],
+        input_path: &Path,
+        args: &[&str],
+        output_path: &Path,
+        on_progress: &mut dyn FnMut(JobProgress),
+    ) -> Result<(), Error> {
+        let duration = probe_duration(input_path);
+        let mut child = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-nostats", "-progress", "pipe:1", "-y"])
+            .args(input_args)
+            .arg("-i")
+            .arg(input_path)
+            .args(args)
+            .arg(output_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::CannotRunFfmpeg)?;
+
+        // stderr is read on a separate thread, so that ffmpeg does not get stuck on a full pipe while progress is being read
+        let mut stderr = child.stderr.take().expect("stderr should be piped");
+        let stderr_thread = thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stderr.read_to_end(&mut output);
+            output
+        });
+
+        let stdout = child.stdout.take().expect("stdout should be piped");
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    // don't leave ffmpeg running (or a zombie process behind) once its progress can't be followed anymore
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let _ = stderr_thread.join();
+                    return Err(Error::CannotRunFfmpeg(e));
+                }
+            };
+            if let Some(duration) = duration
+                && let Some(percent) = parse_ffmpeg_progress_line(&line, duration)
+            {
+                on_progress(JobProgress::new(percent, "encoding"));
+            }
+        }
+
+        let status = child.wait().map_err(Error::CannotRunFfmpeg)?;
+        let stderr = stderr_thread.join().expect("stderr thread should not panic");
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::FfmpegFailed {
+                status: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            })
+        }
+    }
+
+    /// Run the job on a separate thread, giving up on it with [`Error::TimedOut`] if it does not finish within `deadline`.
+    ///
+    /// Jobs cannot be killed, so a job that times out is only asked to stop, the same way as in [`Self::run_cancellable`].
+    /// Jobs that don't check for cancellation keep running on their thread until they finish on their own,
+    /// and their result is discarded - the thread is effectively leaked until then.
+    ///
+    /// A panic inside of the job does not propagate to the current thread - it is returned as [`Error::JobPanicked`] instead.
+    ///
+    /// Progress is passed from the job's thread to `on_progress`, which is called on the current thread.
+    pub fn run_with_deadline(&self, deadline: Duration, on_progress: Option<&mut dyn FnMut(JobProgress)>) -> Result<Success, Error> {
+        let job = self.clone();
+        run_on_thread_with_deadline(deadline, on_progress, move |cancelled, on_progress| {
+            job.run_cancellable(cancelled, Some(on_progress))
+        })
+    }
+
+    fn sleep_cancellable(duration: Duration, cancelled: &AtomicBool) -> Result<(), Error> {
+        let deadline = Instant::now() + duration;
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(Error::Cancelled);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            sleep(remaining.min(Self::CANCELLATION_POLL_INTERVAL));
+        }
+    }
+}
+
+/// Run `f` on a separate thread, giving up on it with [`Error::TimedOut`] if it does not finish within `deadline`.
+///
+/// Once the deadline passes, the flag given to `f` is set and the thread is left to finish on its own - its result is discarded.
+/// Progress reported by `f` is passed to `on_progress` on the current thread.
+/// A panic inside of `f` is returned as [`Error::JobPanicked`], see [`Job::run_with_deadline`].
+pub fn run_on_thread_with_deadline<F>(deadline: Duration, on_progress: Option<&mut dyn FnMut(JobProgress)>, f: F) -> Result<Success, Error>
+where
+    F: FnOnce(&AtomicBool, &mut dyn FnMut(JobProgress)) -> Result<Success, Error> + Send + 'static,
+{
+    enum Message {
+        Progress(JobProgress),
+        Finished(Result<Success, Error>),
+    }
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn({
+        let cancelled = cancelled.clone();
+        move || {
+            // the receiver is gone if the job has timed out already
+            let progress_sender = sender.clone();
+            let mut send_progress = |progress| {
+                let _ = progress_sender.send(Message::Progress(progress));
+            };
+            let _ = sender.send(Message::Finished(f(&cancelled, &mut send_progress)));
+        }
+    });
+
+    let on_progress = match on_progress {
+        Some(on_progress) => on_progress,
+        None => &mut |_| {},
+    };
+    let deadline = Instant::now() + deadline;
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Message::Progress(progress)) => on_progress(progress),
+            Ok(Message::Finished(result)) => return result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                cancelled.store(true, Ordering::Relaxed);
+                return Err(Error::TimedOut);
+            }
+            // the thread only drops the sender without sending a result if it panicked
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                let message = match handle.join() {
+                    Err(payload) => panic_message(payload.as_ref()),
+                    Ok(()) => "job thread exited without a result".to_string(),
+                };
+                return Err(Error::JobPanicked(message));
+            }
+        }
+    }
+}
+
+/// Run `f` on the current thread, turning a panic inside of it into [`Error::JobPanicked`].
+pub fn catch_job_panic(f: impl FnOnce() -> Result<Success, Error>) -> Result<Success, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(Error::JobPanicked(panic_message(payload.as_ref()))))
+}
+
+/// Get the message of a panic from its payload, see [`std::panic::PanicHookInfo::payload`].
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Get the duration of a media file using ffprobe.
+///
+/// Returns [`None`] if ffprobe cannot be run, or if it could not determine the duration.
+pub fn probe_duration(path: &Path) -> Option<Duration> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let seconds: f64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parse one line of ffmpeg's `-progress` output, returning how much of the file has been encoded, from 0 to 100.
+///
+/// Only the `out_time_us` key is used - despite its name, ffmpeg's `out_time_ms` key is in microseconds too.
+/// Returns [`None`] for other keys, and for values which are not known yet (ffmpeg outputs `N/A` for those).
+pub fn parse_ffmpeg_progress_line(line: &str, duration: Duration) -> Option<f64> {
+    let value = line.strip_prefix("out_time_us=")?;
+    let micros: u64 = value.trim().parse().ok()?;
+    if duration.is_zero() {
+        return None;
     }
+    Some((micros as f64 / duration.as_micros() as f64 * 100.0).min(100.0))
 }
@@ -1,6 +1,9 @@
 use crate::hive::task::{Task, TaskState};
 use crate::util::file_ex::FileEx;
 use crate::util::lockfile::{self, LockfileHandle};
+use crate::util::timestamp::NsTimestamp;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::path::Path;
 use uuid::Uuid;
@@ -33,12 +36,91 @@ impl fmt::Display for TaskNotFound {
 impl std::error::Error for TaskNotFound {}
 
 impl TaskQueue {
+    /// Standard filename used for the task queue.
+    pub const STANDARD_FILENAME: &str = "task_queue.jsonl";
+
+    /// Standard filename used for the dead-letter queue, see [`Self::move_failed_to_dead_letter`].
+    pub const DEAD_LETTER_FILENAME: &str = "task_deadletter.jsonl";
+
+    /// Sorting key of queued tasks - the task with the smallest key should be taken off the queue first.
+    ///
+    /// Tasks are ordered by their [`Task::priority`] (highest first), and then by their request timestamp (oldest first).
+    fn queue_order_key(task: &Task) -> (Reverse<i32>, NsTimestamp) {
+        (Reverse(task.priority), task.request_timestamp)
+    }
+
+    /// Find the queued task that should be taken off the queue next, see [`Self::queue_order_key`].
     pub fn top_queued_task(&self) -> Option<&Task> {
-        self.tasks.iter().find(|task| task.state == TaskState::Queued)
+        self.tasks
+            .iter()
+            .filter(|task| task.state == TaskState::Queued)
+            .min_by_key(|task| Self::queue_order_key(task))
     }
 
+    /// Find the queued task that should be taken off the queue next, see [`Self::queue_order_key`].
     pub fn top_queued_task_mut(&mut self) -> Option<&mut Task> {
-        self.tasks.iter_mut().find(|task| task.state == TaskState::Queued)
+        self.tasks
+            .iter_mut()
+            .filter(|task| task.state == TaskState::Queued)
+            .min_by_key(|task| Self::queue_order_key(task))
+    }
+
+    /// Check the state of a task, looking it up by UUID.
+    fn task_state(&self, task_uuid: Uuid) -> Option<&TaskState> {
+        self.get_task(task_uuid).map(|task| &task.state)
+    }
+
+    /// Are all dependencies of the task done?
+    ///
+    /// Dependencies that are not in this queue (for example ones that have been moved to the dead-letter queue) are not considered done.
+    fn are_dependencies_done(&self, task: &Task) -> bool {
+        task.depends_on
+            .iter()
+            .all(|dependency| self.task_state(dependency.0) == Some(&TaskState::Done))
+    }
+
+    /// Find the queued task that should be taken off the queue next, considering only tasks with all of their dependencies done.
+    ///
+    /// Tasks are ordered the same way as in [`Self::top_queued_task`].
+    /// Tasks with a failed dependency are never eligible - they can be found with [`Self::tasks_with_failed_dependencies`].
+    pub fn top_eligible_task_mut(&mut self) -> Option<&mut Task> {
+        let (i, _) = self
+            .tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| task.state == TaskState::Queued && self.are_dependencies_done(task))
+            .min_by_key(|(_, task)| Self::queue_order_key(task))?;
+        Some(&mut self.tasks[i])
+    }
+
+    /// Find queued tasks that cannot ever become eligible, because at least one of their dependencies has failed or has been cancelled.
+    pub fn tasks_with_failed_dependencies(&self) -> Vec<&Task> {
+        self.tasks
+            .iter()
+            .filter(|task| task.state == TaskState::Queued)
+            .filter(|task| {
+                task.depends_on
+                    .iter()
+                    .any(|dependency| matches!(self.task_state(dependency.0), Some(TaskState::Failed | TaskState::Cancelled)))
+            })
+            .collect()
+    }
+
+    /// Cancel a task that has not been started yet, so that it is never taken off the queue.
+    ///
+    /// Returns `true` if the task was cancelled, or `false` if it was not in the [`TaskState::Queued`] state.
+    /// Tasks that are already being worked on have to be cancelled by the process running them, see [`crate::hive::job::Job::run_cancellable`].
+    ///
+    /// # Errors
+    /// If the queue does not have a task with this UUID, nothing happens and an Err variant is returned.
+    pub fn cancel_task(&mut self, task_uuid: Uuid) -> Result<bool, TaskNotFound> {
+        let task = self.get_task_mut(task_uuid).ok_or(TaskNotFound)?;
+        if task.state != TaskState::Queued {
+            return Ok(false);
+        }
+        task.state = TaskState::Cancelled;
+        task.finish_timestamp = Some(NsTimestamp::now());
+        Ok(true)
     }
 
     /// Add a new task.
@@ -85,6 +167,96 @@ impl TaskQueue {
         }
     }
 
+    /// Remove the task with the given UUID from the queue, returning it.
+    ///
+    /// Returns [`None`] if the queue does not have a task with this UUID.
+    pub fn remove_task(&mut self, task_uuid: Uuid) -> Option<Task> {
+        let position = self.tasks.iter().position(|task| task.uuid.0 == task_uuid)?;
+        Some(self.tasks.remove(position))
+    }
+
+    /// Remove all finished tasks - ones that are done, failed or cancelled.
+    ///
+    /// Done tasks that are a dependency of a task which has not finished yet are kept, so that the dependent task can still become eligible,
+    /// see [`Self::top_eligible_task_mut`].
+    ///
+    /// Returns the amount of tasks removed.
+    pub fn purge_finished(&mut self) -> usize {
+        let needed_dependencies: HashSet<Uuid> = self
+            .tasks
+            .iter()
+            .filter(|task| matches!(task.state, TaskState::Queued | TaskState::Working))
+            .flat_map(|task| task.depends_on.iter().map(|dependency| dependency.0))
+            .collect();
+        let previous_len = self.tasks.len();
+        self.tasks.retain(|task| match task.state {
+            TaskState::Queued | TaskState::Working => true,
+            TaskState::Done => needed_dependencies.contains(&task.uuid.0),
+            TaskState::Failed | TaskState::Cancelled => false,
+        });
+        previous_len - self.tasks.len()
+    }
+
+    /// Move all failed and cancelled tasks into a dead-letter queue.
+    ///
+    /// The dead-letter queue is a separate task queue (usually stored as [`Self::DEAD_LETTER_FILENAME`]),
+    /// which keeps permanently failed and cancelled tasks out of the way of the live queue, while still allowing them to be reviewed and retried later.
+    ///
+    /// Returns the amount of tasks moved. Neither of the queues is written to disk by this function.
+    pub fn move_failed_to_dead_letter(&mut self, dead_letter: &mut TaskQueue) -> usize {
+        let (failed, remaining): (Vec<Task>, Vec<Task>) =
+            self.tasks.drain(..).partition(|task| matches!(task.state, TaskState::Failed | TaskState::Cancelled));
+        self.tasks = remaining;
+        let count = failed.len();
+        for task in failed {
+            dead_letter.add_or_update_task(task);
+        }
+        count
+    }
+
+    /// Move a task from the dead-letter queue back into this queue, so that it can be retried.
+    ///
+    /// The task is reset to the [`TaskState::Queued`] state, its previous start, finish, worker and result information is cleared,
+    /// and its attempt counter is reset.
+    ///
+    /// # Errors
+    /// If the dead-letter queue does not have a task with this UUID, nothing happens and an Err variant is returned.
+    pub fn requeue_from_dead_letter(&mut self, dead_letter: &mut TaskQueue, task_uuid: Uuid) -> Result<(), TaskNotFound> {
+        let position = dead_letter
+            .tasks
+            .iter()
+            .position(|task| task.uuid.0 == task_uuid)
+            .ok_or(TaskNotFound)?;
+        let mut task = dead_letter.tasks.remove(position);
+        task.state = TaskState::Queued;
+        task.start_timestamp = None;
+        task.worker_pid = None;
+        task.finish_timestamp = None;
+        task.results = None;
+        task.attempts = 0;
+        self.add_or_update_task(task);
+        Ok(())
+    }
+
+    /// Find tasks that are currently in the given state.
+    pub fn tasks_by_state(&self, state: TaskState) -> Vec<&Task> {
+        self.tasks.iter().filter(|task| task.state == state).collect()
+    }
+
+    /// Find tasks that have been taken on by the worker process with the given PID.
+    pub fn tasks_by_worker_pid(&self, worker_pid: u32) -> Vec<&Task> {
+        self.tasks.iter().filter(|task| task.worker_pid == Some(worker_pid)).collect()
+    }
+
+    /// Count the tasks in each state. States without any tasks are not included.
+    pub fn counts_by_state(&self) -> HashMap<TaskState, usize> {
+        let mut counts = HashMap::new();
+        for task in &self.tasks {
+            *counts.entry(task.state.clone()).or_default() += 1;
+        }
+        counts
+    }
+
     pub fn get_task(&self, task_uuid: Uuid) -> Option<&Task> {
         self.tasks.iter().find(|task| task.uuid.0 == task_uuid)
     }
@@ -1,10 +1,12 @@
-use crate::hive::job::Job;
+use crate::hive::job::{self, Job, Success};
 use crate::util::timestamp::NsTimestamp;
 use crate::util::uuid::UuidString;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use serde_json::json;
+use std::process;
+use std::time::Duration;
 
-#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskState {
     #[default]
@@ -12,6 +14,7 @@ pub enum TaskState {
     Working,
     Done,
     Failed,
+    Cancelled,
 }
 
 pub type TaskResults = serde_json::Value;
@@ -28,12 +31,40 @@ pub struct Task {
     pub worker_pid: Option<u32>,
     pub finish_timestamp: Option<NsTimestamp>,
     pub results: Option<TaskResults>,
+
+    /// How many times the job of this task has been started.
+    #[serde(default)]
+    pub attempts: u32,
+
+    /// How many times the job can be started before the task is marked as [`TaskState::Failed`].
+    ///
+    /// Tasks written before this field was introduced are not retried.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Tasks with a higher priority are taken off the queue first. Tasks with the same priority are taken in the order they were requested.
+    #[serde(default)]
+    pub priority: i32,
+
+    /// UUIDs of tasks that have to be [`TaskState::Done`] before this task can be started.
+    #[serde(default)]
+    pub depends_on: Vec<UuidString>,
+
+    /// How long (in nanoseconds) a single attempt of the job can run before it fails with [`job::Error::TimedOut`].
+    ///
+    /// Set this to [`None`] to let the job run for as long as it needs.
+    #[serde(default)]
+    pub deadline_nanos: Option<u64>,
+}
+
+fn default_max_attempts() -> u32 {
+    1
 }
 
 impl Task {
     pub fn new(name: String, job: Job) -> Self {
         Self {
-            uuid: Uuid::new_v4().into(),
+            uuid: UuidString::new_v4(),
             name,
             comment: None,
             job,
@@ -43,6 +74,61 @@ impl Task {
             worker_pid: None,
             finish_timestamp: None,
             results: None,
+            attempts: 0,
+            max_attempts: default_max_attempts(),
+            priority: 0,
+            depends_on: Vec::new(),
+            deadline_nanos: None,
         }
     }
+
+    /// Mark the task as being worked on by this process, counting it as a new attempt.
+    pub fn start(&mut self) {
+        self.state = TaskState::Working;
+        self.start_timestamp = Some(NsTimestamp::now());
+        self.worker_pid = Some(process::id());
+        self.attempts += 1;
+    }
+
+    /// Record the outcome of the current attempt.
+    ///
+    /// A failed attempt puts the task back into the [`TaskState::Queued`] state if it has attempts left, and marks it as [`TaskState::Failed`] otherwise.
+    /// Either way, the error of the last attempt is kept in [`Self::results`]. A successful attempt stores the [`Success`] in [`Self::results`] instead. Cancelled jobs are not retried, and mark the task as [`TaskState::Cancelled`].
+    pub fn finish(&mut self, result: Result<Success, job::Error>) {
+        match result {
+            Ok(success) => {
+                self.state = TaskState::Done;
+                self.finish_timestamp = Some(NsTimestamp::now());
+                self.results = Some(serde_json::to_value(success).expect("job results should be serializable"));
+            }
+            Err(job::Error::Cancelled) => {
+                self.state = TaskState::Cancelled;
+                self.finish_timestamp = Some(NsTimestamp::now());
+            }
+            Err(e) => {
+                self.results = Some(json!({ "error": e.to_string() }));
+                if self.attempts < self.max_attempts {
+                    self.state = TaskState::Queued;
+                    self.start_timestamp = None;
+                    self.worker_pid = None;
+                } else {
+                    self.state = TaskState::Failed;
+                    self.finish_timestamp = Some(NsTimestamp::now());
+                }
+            }
+        }
+    }
+
+    /// Run one attempt of the task's job, see [`Self::start`] and [`Self::finish`].
+    ///
+    /// The job runs in the current thread, unless the task has a deadline - see [`Job::run_with_deadline`].
+    /// Either way, a panicking job fails the attempt with [`job::Error::JobPanicked`] instead of unwinding through the caller.
+    pub fn execute(&mut self) {
+        self.start();
+        let result = match self.deadline_nanos {
+            Some(deadline_nanos) => self.job.run_with_deadline(Duration::from_nanos(deadline_nanos), None),
+            None => self.job.run(None),
+        };
+        self.finish(result);
+    }
 }
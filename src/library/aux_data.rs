@@ -1,13 +1,96 @@
 use crate::util::file_ex::FileEx;
 use crate::util::lockfile::{self, LockfileHandle};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 use std::path::Path;
 
+/// An RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Color used for tags that don't have a color chosen by the user.
+    pub const DEFAULT: Self = Self { r: 0x80, g: 0x80, b: 0x80 };
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parse a color written as `#rrggbb`. Both lowercase and uppercase hex digits are accepted.
+    ///
+    /// # Errors
+    /// Returns an Err variant if the string does not start with `#` or does not contain exactly 6 hex digits.
+    /// The shorthand `#rgb` notation is not supported.
+    pub fn from_hex(hex: &str) -> Result<Self, InvalidHexColor> {
+        let digits = hex.strip_prefix('#').ok_or(InvalidHexColor)?;
+        if digits.len() != 6 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(InvalidHexColor);
+        }
+        let component = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| InvalidHexColor);
+        Ok(Self::new(component(0)?, component(2)?, component(4)?))
+    }
+
+    /// Write the color as `#rrggbb`, with lowercase hex digits.
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidHexColor;
+
+impl fmt::Display for InvalidHexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "color is not in the #rrggbb format")
+    }
+}
+
+impl std::error::Error for InvalidHexColor {}
+
+/// Tag colors used to be stored as `null`, so a missing color is replaced with [`Color::DEFAULT`].
+fn deserialize_tag_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    Ok(Option::<Color>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug)]
+pub struct TagExists;
+
+impl fmt::Display for TagExists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tag with this id already exists")
+    }
+}
+
+impl std::error::Error for TagExists {}
+
+#[derive(Debug)]
+pub struct TagNotFound;
+
+impl fmt::Display for TagNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tag with this id was not found")
+    }
+}
+
+impl std::error::Error for TagNotFound {}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TagInfo {
     pub id: String,
     pub name: String,
-    pub color: (), // TODO
+    #[serde(default, deserialize_with = "deserialize_tag_color")]
+    pub color: Color,
 }
 
 /// Auxiliary data for the library.
@@ -30,7 +113,89 @@ impl LibraryAuxData {
     pub fn read_or_create_new_safe<P: AsRef<Path>>(path: P) -> lockfile::Result<Self> {
         let lockfile = LockfileHandle::acquire_wait(path)?;
         let inner = lockfile.read_from_json()?.unwrap_or_default();
-        Ok(Self { inner, lockfile })
+        let aux_data = Self { inner, lockfile };
+        if let Err(duplicated_ids) = aux_data.validate() {
+            eprintln!(
+                "warning: library aux data at {:?} contains duplicated tag ids: {duplicated_ids:?}",
+                aux_data.lockfile.main_file_path()
+            );
+        }
+        Ok(aux_data)
+    }
+
+    /// Check that every tag id is unique.
+    ///
+    /// # Errors
+    /// Returns the list of ids used by more than one tag, each listed once.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut duplicated_ids = Vec::new();
+        for tag in &self.inner.tags {
+            if !seen.insert(tag.id.as_str()) && !duplicated_ids.contains(&tag.id) {
+                duplicated_ids.push(tag.id.clone());
+            }
+        }
+        if duplicated_ids.is_empty() { Ok(()) } else { Err(duplicated_ids) }
+    }
+
+    /// Remove tags with duplicated ids, keeping only the first tag with each id.
+    ///
+    /// Returns the number of removed tags.
+    pub fn dedup_tags(&mut self) -> usize {
+        let previous_len = self.inner.tags.len();
+        let mut seen = HashSet::new();
+        self.inner.tags.retain(|tag| seen.insert(tag.id.clone()));
+        previous_len - self.inner.tags.len()
+    }
+
+    pub fn tags(&self) -> &[TagInfo] {
+        &self.inner.tags
+    }
+
+    pub fn get_tag(&self, id: &str) -> Option<&TagInfo> {
+        self.inner.tags.iter().find(|tag| tag.id == id)
+    }
+
+    fn get_tag_mut(&mut self, id: &str) -> Option<&mut TagInfo> {
+        self.inner.tags.iter_mut().find(|tag| tag.id == id)
+    }
+
+    /// Add a new tag.
+    ///
+    /// # Errors
+    /// Tag ids have to be unique - if a tag with the same id already exists, nothing happens and an Err variant is returned.
+    pub fn add_tag(&mut self, tag: TagInfo) -> Result<(), TagExists> {
+        if self.get_tag(&tag.id).is_some() {
+            return Err(TagExists);
+        }
+        self.inner.tags.push(tag);
+        Ok(())
+    }
+
+    /// Remove the tag with the given id, returning it.
+    ///
+    /// Library entries that have this tag assigned are not changed.
+    pub fn remove_tag(&mut self, id: &str) -> Option<TagInfo> {
+        let i = self.inner.tags.iter().position(|tag| tag.id == id)?;
+        Some(self.inner.tags.remove(i))
+    }
+
+    /// Change the display name of a tag. The id of the tag stays the same.
+    ///
+    /// # Errors
+    /// If there is no tag with this id, nothing happens and an Err variant is returned.
+    pub fn rename_tag(&mut self, id: &str, new_name: String) -> Result<(), TagNotFound> {
+        self.get_tag_mut(id).ok_or(TagNotFound)?.name = new_name;
+        Ok(())
+    }
+
+    /// Change the color of a tag.
+    ///
+    /// # Errors
+    /// If there is no tag with this id, nothing happens and an Err variant is returned.
+    pub fn set_tag_color(&mut self, id: &str, color: Color) -> Result<(), TagNotFound> {
+        self.get_tag_mut(id).ok_or(TagNotFound)?.color = color;
+        Ok(())
     }
 
     pub fn write_to_file(&self) -> lockfile::Result<()> {
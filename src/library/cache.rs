@@ -2,10 +2,17 @@ use crate::library::index::LibraryIndex;
 use crate::util::error::UNSUPPORTED_TIMESTAMP_MESSAGE;
 use crate::util::file_ex::{self, FileEx};
 use crate::util::timestamp::NsTimestamp;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+#[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use walkdir::WalkDir;
 
 /// Library cache entry for one file.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -23,7 +30,15 @@ pub struct FileCacheInfo {
     pub modify_timestamp: NsTimestamp,
 
     /// SHA256 hash in a hexadecimal string format.
+    ///
+    /// Despite the name, this is the hash computed using [`Self::hashing_method`], which does not have to be SHA256.
     pub sha256: String,
+
+    /// Method that was used to compute [`Self::sha256`].
+    ///
+    /// Cache files written before this field was introduced only contain SHA256 hashes, so this defaults to [`HashingMethod::SHA256`].
+    #[serde(default)]
+    pub hashing_method: HashingMethod,
 }
 
 /// Inner structure for [`LibraryCache`].
@@ -44,11 +59,20 @@ pub struct LibraryCacheInner {
 /// If any of these values are not identical to an entry in the cache, the file can be assumed to be different, and the hash can be recalculated.
 /// The newly calculated hash can also be added to the cache for future use.
 ///
-/// This is a wrapper structure for [`LibraryCacheInner`]. Apart from the data, it also contains [`Self::cache_file_path`], which is the path of the cache file.
+/// This is a wrapper structure for [`LibraryCacheInner`]. Apart from the data, it also contains [`Self::cache_file_path`], which is the path of the cache file,
+/// and [`Self::filename_index`], which speeds up cache lookups.
 #[derive(Debug, Clone)]
 pub struct LibraryCache {
     inner: LibraryCacheInner,
     cache_file_path: PathBuf,
+
+    /// Map of 'filename' : 'indices of entries in [`LibraryCacheInner::files`] with that filename'.
+    ///
+    /// This is not saved to the cache file - it is rebuilt whenever the cache is loaded, and kept up to date by [`Self::insert`].
+    filename_index: HashMap<String, Vec<usize>>,
+
+    /// Method used to compute new hashes. Only cache entries computed using this method are used.
+    hashing_method: HashingMethod,
 }
 
 impl LibraryCache {
@@ -65,6 +89,18 @@ impl LibraryCache {
     /// Standard filename used for library cache
     pub const STANDARD_FILENAME: &str = "library_cache.json";
 
+    /// Returns the method used to compute new hashes.
+    pub fn hashing_method(&self) -> HashingMethod {
+        self.hashing_method
+    }
+
+    /// Sets the method used to compute new hashes.
+    ///
+    /// Entries computed using a different method stay in the cache, but are ignored until the method is changed back.
+    pub fn set_hashing_method(&mut self, hashing_method: HashingMethod) {
+        self.hashing_method = hashing_method;
+    }
+
     /// Function for filtering the cache to find a specific entry.
     ///
     /// This function takes some parameters and returns a predicate function that can filter the cache for specific results.
@@ -74,33 +110,42 @@ impl LibraryCache {
         file_size: u64,
         birth_timestamp: NsTimestamp,
         modify_timestamp: NsTimestamp,
+        hashing_method: HashingMethod,
     ) -> impl Fn(&&FileCacheInfo) -> bool {
         move |cache_entry| {
-            cache_entry.file_size == file_size
+            cache_entry.hashing_method == hashing_method
+                && cache_entry.file_size == file_size
                 && cache_entry.birth_timestamp == birth_timestamp
                 && cache_entry.modify_timestamp == modify_timestamp
                 && cache_entry.filename == filename
         }
     }
 
-    /// Function for filtering the cache to find a specific entry.
-    ///
-    /// This function takes some parameters and returns a predicate function that can filter the cache for specific results.
-    /// This function can operate over mutable references to [`FileCacheInfo`].
+    /// Finds the index of the cache entry matching the file's identifying characteristics.
     ///
-    /// The returned predicate transforms the mutable reference into an immutable reference and reuses [`Self::cache_find_predicate`] to provide the same functionality.
-    fn cache_find_predicate_mut(
-        filename: &str,
-        file_size: u64,
-        birth_timestamp: NsTimestamp,
-        modify_timestamp: NsTimestamp,
-    ) -> impl Fn(&&mut FileCacheInfo) -> bool {
-        move |x| {
-            let y = &&**x;
-            Self::cache_find_predicate(filename, file_size, birth_timestamp, modify_timestamp)(y)
+    /// Only entries with a matching filename are checked, using [`Self::filename_index`].
+    fn find_entry_index(&self, filename: &str, file_size: u64, birth_timestamp: NsTimestamp, modify_timestamp: NsTimestamp) -> Option<usize> {
+        let predicate = Self::cache_find_predicate(filename, file_size, birth_timestamp, modify_timestamp, self.hashing_method);
+        self.filename_index
+            .get(filename)?
+            .iter()
+            .copied()
+            .find(|&i| predicate(&&self.inner.files[i]))
+    }
+
+    /// Rebuilds [`Self::filename_index`] from scratch based on the current cache entries.
+    fn rebuild_filename_index(&mut self) {
+        self.filename_index.clear();
+        for (i, cache_entry) in self.inner.files.iter().enumerate() {
+            self.filename_index.entry(cache_entry.filename.clone()).or_default().push(i);
         }
     }
 
+    /// Returns all entries stored in the cache.
+    pub fn entries(&self) -> &[FileCacheInfo] {
+        &self.inner.files
+    }
+
     /// Finds the cached SHA256 hash of a file given the file's identifying characteristics.
     ///
     /// This function takes the name of the file, the size of the file, as well as the file's birth timestamp and modification timestamp,
@@ -114,56 +159,125 @@ impl LibraryCache {
         birth_timestamp: NsTimestamp,
         modify_timestamp: NsTimestamp,
     ) -> Option<String> {
-        self.inner
-            .files
-            .iter()
-            .find(Self::cache_find_predicate(filename, file_size, birth_timestamp, modify_timestamp))
-            .map(|cache_entry| cache_entry.sha256.clone())
+        self.find_entry_index(filename, file_size, birth_timestamp, modify_timestamp)
+            .map(|i| self.inner.files[i].sha256.clone())
+    }
+
+    /// Returns whether the file at the given path has an up-to-date hash recorded in the cache.
+    ///
+    /// Returns `false` if the metadata of the file could not be read.
+    pub fn is_file_cached(&self, path: &Path) -> bool {
+        Self::file_identifiers(path).is_ok_and(|(filename, file_size, birth_timestamp, modify_timestamp)| {
+            self.find_entry_index(&filename, file_size, birth_timestamp, modify_timestamp).is_some()
+        })
     }
 
     /// Finds the cached SHA256 hash of a file, or computes the hash if it is not cached yet.
     ///
     /// This function takes in a path of the file, and returns a SHA256 hash. The function uses the cache to avoid doing repeated calculations.
+    /// If [`Self::hashing_method`] was changed, the hash is computed using that method instead - such hashes must not be stored in library entries,
+    /// which are always looked up by SHA256.
     ///
     /// If this file has not been recorded in the cache yet, this function will read in the whole file,
     /// compute the hash of the file, update the cache file and save it to disk automatically.
-    pub fn find_or_compute_file_sha256_hash(&mut self, path: &Path) -> String {
-        let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
-        let file_size = fs::metadata(path).unwrap().size();
-        let birth_timestamp = fs::metadata(path)
-            .unwrap()
-            .created()
-            .unwrap()
-            .try_into()
-            .expect(UNSUPPORTED_TIMESTAMP_MESSAGE);
-        let modify_timestamp = fs::metadata(path)
-            .unwrap()
-            .modified()
-            .unwrap()
-            .try_into()
-            .expect(UNSUPPORTED_TIMESTAMP_MESSAGE);
+    ///
+    /// # Errors
+    /// This function returns [`file_ex::Error::CannotReadFile`] if the metadata or the contents of the file could not be read,
+    /// and passes through any error encountered while autosaving the cache.
+    pub fn find_or_compute_file_sha256_hash(&mut self, path: &Path) -> file_ex::Result<String> {
+        let (filename, file_size, birth_timestamp, modify_timestamp) = Self::file_identifiers(path)?;
 
         if let Some(cached_hash) = self.find_cached_sha256_hash(&filename, file_size, birth_timestamp, modify_timestamp) {
             if LibraryIndex::VERBOSE_SCANNING {
                 println!("[scan] using cached hash for {path:?}: {cached_hash}");
             }
-            cached_hash
+            Ok(cached_hash)
         } else {
-            let computed_hash = compute_hash_of_file(path);
-            self.insert(filename, file_size, birth_timestamp, modify_timestamp, computed_hash.clone());
-
+            let computed_hash = self.compute_and_insert(path, filename, file_size, birth_timestamp, modify_timestamp)?;
             if Self::AUTOSAVE {
-                self.write_to_file().expect("could not autosave cache to file");
+                self.write_to_file()?;
             }
+            Ok(computed_hash)
+        }
+    }
+
+    /// Computes the hash of a file without looking it up in the cache first, and updates the cache with the result.
+    ///
+    /// This is meant for integrity checks, where a cached hash cannot be trusted.
+    /// The cache is not autosaved, since integrity checks usually go over many files - call [`Self::write_to_file`] once they are all done.
+    ///
+    /// # Errors
+    /// This function returns [`file_ex::Error::CannotReadFile`] if the metadata or the contents of the file could not be read.
+    pub fn recompute_file_hash(&mut self, path: &Path) -> file_ex::Result<String> {
+        let (filename, file_size, birth_timestamp, modify_timestamp) = Self::file_identifiers(path)?;
+        self.compute_and_insert(path, filename, file_size, birth_timestamp, modify_timestamp)
+    }
+
+    /// Computes the hash of a file and inserts it into the cache, without saving the cache to disk.
+    fn compute_and_insert(
+        &mut self,
+        path: &Path,
+        filename: String,
+        file_size: u64,
+        birth_timestamp: NsTimestamp,
+        modify_timestamp: NsTimestamp,
+    ) -> file_ex::Result<String> {
+        let computed_hash = compute_hash_of_file(path, self.hashing_method)?;
+        self.insert(filename, file_size, birth_timestamp, modify_timestamp, computed_hash.clone());
+        Ok(computed_hash)
+    }
+
+    /// Computes the hashes of all given files that are not cached yet, using multiple threads.
+    ///
+    /// The files missing from the cache are hashed in parallel on the [`rayon`] thread pool, and the results are inserted into the cache.
+    /// Unlike [`Self::find_or_compute_file_sha256_hash`], the cache is autosaved only once, after all of the hashes have been computed.
+    ///
+    /// Files whose metadata could not be read are ignored here - they will produce an error once they are looked up with [`Self::find_or_compute_file_sha256_hash`].
+    ///
+    /// Returns the number of newly computed hashes.
+    pub fn compute_missing_hashes_parallel(&mut self, paths: &[&Path]) -> file_ex::Result<usize> {
+        let missing: Vec<_> = paths
+            .iter()
+            .filter_map(|path| Self::file_identifiers(path).ok().map(|identifiers| (*path, identifiers)))
+            .filter(|(_, (filename, file_size, birth_timestamp, modify_timestamp))| {
+                self.find_entry_index(filename, *file_size, *birth_timestamp, *modify_timestamp).is_none()
+            })
+            .collect();
 
-            computed_hash
+        let previous_len = self.inner.files.len();
+        let hashing_method = self.hashing_method;
+        let cache = Mutex::new(&mut *self);
+        missing
+            .into_par_iter()
+            .for_each(|(path, (filename, file_size, birth_timestamp, modify_timestamp))| {
+                match compute_hash_of_file(path, hashing_method) {
+                    Ok(computed_hash) => cache
+                        .lock()
+                        .unwrap()
+                        .insert(filename, file_size, birth_timestamp, modify_timestamp, computed_hash),
+                    Err(e) => eprintln!("warning: could not compute hash for {path:?}: {e:?}"),
+                }
+            });
+
+        let computed_count = self.inner.files.len() - previous_len;
+        if Self::AUTOSAVE && computed_count > 0 {
+            self.write_to_file()?;
         }
+        Ok(computed_count)
+    }
+
+    /// Reads the characteristics identifying a file in the cache: the filename, the file size, the birth timestamp and the modification timestamp.
+    fn file_identifiers(path: &Path) -> file_ex::Result<(String, u64, NsTimestamp, NsTimestamp)> {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let (file_size, birth_timestamp, modify_timestamp) = file_metadata(path).map_err(file_ex::Error::CannotReadFile)?;
+        Ok((filename, file_size, birth_timestamp, modify_timestamp))
     }
 
     /// Inserts a new cache entry or updates an existing one.
     ///
-    /// This function takes in identifiers of a file and the SHA256 hash of that file, and either adds a new cache entry,
+    /// This function takes in identifiers of a file and the hash of that file, and either adds a new cache entry,
     /// or updates an existing one if the identifiers match up with an existing cache entry.
+    /// The hash is assumed to be computed using the current [`Self::hashing_method`].
     pub fn insert(
         &mut self,
         filename: String,
@@ -172,28 +286,44 @@ impl LibraryCache {
         modify_timestamp: NsTimestamp,
         sha256: String,
     ) {
-        if let Some(existing) = self.inner.files.iter_mut().find(Self::cache_find_predicate_mut(
-            &filename,
-            file_size,
-            birth_timestamp,
-            modify_timestamp,
-        )) {
-            existing.filename = filename;
-            existing.file_size = file_size;
-            existing.birth_timestamp = birth_timestamp;
-            existing.modify_timestamp = modify_timestamp;
-            existing.sha256 = sha256;
+        if let Some(i) = self.find_entry_index(&filename, file_size, birth_timestamp, modify_timestamp) {
+            // all identifying fields already match, only the hash can change
+            self.inner.files[i].sha256 = sha256;
         } else {
+            self.filename_index.entry(filename.clone()).or_default().push(self.inner.files.len());
             self.inner.files.push(FileCacheInfo {
                 filename,
                 birth_timestamp,
                 modify_timestamp,
                 file_size,
                 sha256,
+                hashing_method: self.hashing_method,
             });
         }
     }
 
+    /// Removes cache entries of files that no longer exist in the library.
+    ///
+    /// The cache only records filenames, so an entry is kept as long as any file with the same name exists anywhere under `library_dir`.
+    /// The pruned cache is not saved to disk automatically - use [`Self::write_to_file`] afterwards.
+    ///
+    /// Returns the number of removed entries.
+    pub fn prune_missing(&mut self, library_dir: &Path) -> usize {
+        let existing_filenames: HashSet<String> = WalkDir::new(library_dir)
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .filter(|dir_entry| dir_entry.file_type().is_file())
+            .map(|dir_entry| dir_entry.file_name().to_string_lossy().to_string())
+            .collect();
+
+        let previous_len = self.inner.files.len();
+        self.inner
+            .files
+            .retain(|cache_entry| existing_filenames.contains(&cache_entry.filename));
+        self.rebuild_filename_index();
+        previous_len - self.inner.files.len()
+    }
+
     /// Loads cache data from a file or creates a new cache.
     ///
     /// This function loads the cache from a JSON file at the provided file path, or creates a new cache structure if the file does not exist.
@@ -214,7 +344,14 @@ impl LibraryCache {
         }
 
         let inner = inner_opt.unwrap_or_default();
-        Ok(Self { inner, cache_file_path })
+        let mut cache = Self {
+            inner,
+            cache_file_path,
+            filename_index: HashMap::new(),
+            hashing_method: HashingMethod::default(),
+        };
+        cache.rebuild_filename_index();
+        Ok(cache)
     }
 
     /// Saves the cache file to disk.
@@ -232,30 +369,110 @@ impl LibraryCache {
     }
 }
 
-#[derive(Debug)]
-enum HashingMethod {
+/// Reads the size, the birth timestamp and the modification timestamp of a file, using a single `stat()` call.
+///
+/// Some platforms and filesystems do not record the birth timestamp of files - in that case, the modification timestamp is used in its place.
+pub fn file_metadata(path: &Path) -> io::Result<(u64, NsTimestamp, NsTimestamp)> {
+    let metadata = fs::metadata(path)?;
+
+    #[cfg(unix)]
+    let file_size = metadata.size();
+    #[cfg(not(unix))]
+    let file_size = metadata.len();
+
+    let modify_time = metadata.modified()?;
+    let birth_time = metadata.created().unwrap_or(modify_time);
+
+    let birth_timestamp = birth_time.try_into().expect(UNSUPPORTED_TIMESTAMP_MESSAGE);
+    let modify_timestamp = modify_time.try_into().expect(UNSUPPORTED_TIMESTAMP_MESSAGE);
+    Ok((file_size, birth_timestamp, modify_timestamp))
+}
+
+/// Algorithm used to compute hashes of library files.
+///
+/// All hashes are stored as lowercase hexadecimal strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashingMethod {
+    #[default]
     SHA256,
-    #[allow(unused)]
     MD5,
+    BLAKE3,
+}
+
+/// Size of the chunks in which files are read while computing their hashes.
+const HASHING_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Incremental hasher for any of the supported [`HashingMethod`]s.
+enum Hasher {
+    SHA256(Sha256),
+    MD5(md5::Context),
+    BLAKE3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(method: HashingMethod) -> Self {
+        match method {
+            HashingMethod::SHA256 => Self::SHA256(Sha256::new()),
+            HashingMethod::MD5 => Self::MD5(md5::Context::new()),
+            HashingMethod::BLAKE3 => Self::BLAKE3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::SHA256(hasher) => hasher.update(bytes),
+            Self::MD5(context) => context.consume(bytes),
+            Self::BLAKE3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        match self {
+            Self::SHA256(hasher) => hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect(),
+            Self::MD5(context) => format!("{:x}", context.finalize()),
+            Self::BLAKE3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
 }
 
-pub fn compute_hash_of_file(path: &Path) -> String {
+/// Computes the hash of the given bytes using the given method.
+pub fn compute_hash_of_bytes(bytes: &[u8], method: HashingMethod) -> String {
+    let mut hasher = Hasher::new(method);
+    hasher.update(bytes);
+    hasher.finalize_hex()
+}
+
+/// Computes the hash of a file using the given method.
+///
+/// The file is streamed through the hasher in chunks of [`HASHING_CHUNK_SIZE`] bytes, so that large files are never loaded into memory whole.
+///
+/// # Errors
+/// This function returns [`file_ex::Error::CannotReadFile`] if the file could not be opened or read.
+pub fn compute_hash_of_file(path: &Path, method: HashingMethod) -> file_ex::Result<String> {
     // note: changing the hashing method does not change the behaviour/naming of the hash in other places - everywhere else its still called sha256
-    const METHOD: HashingMethod = HashingMethod::SHA256;
     if LibraryIndex::VERBOSE_SCANNING {
-        println!("[scan] computing hash for {path:?} using {METHOD:?}...");
+        println!("[scan] computing hash for {path:?} using {method:?}...");
     }
 
-    let bytes = std::fs::read(path).unwrap();
-    let hash = match METHOD {
-        HashingMethod::SHA256 => sha256::digest(&bytes),
-        HashingMethod::MD5 => {
-            format!("{:x}", md5::compute(&bytes))
+    let file = File::open(path).map_err(file_ex::Error::CannotReadFile)?;
+    let mut reader = BufReader::with_capacity(HASHING_CHUNK_SIZE, file);
+    let mut hasher = Hasher::new(method);
+    loop {
+        let chunk = reader.fill_buf().map_err(file_ex::Error::CannotReadFile)?;
+        if chunk.is_empty() {
+            break;
         }
-    };
+        let chunk_len = chunk.len();
+        hasher.update(chunk);
+        reader.consume(chunk_len);
+    }
+    let hash = hasher.finalize_hex();
 
     if LibraryIndex::VERBOSE_SCANNING {
-        println!("[scan] computing hash for {path:?} using {METHOD:?}... done: {hash}");
+        println!("[scan] computing hash for {path:?} using {method:?}... done: {hash}");
     }
-    hash
+    Ok(hash)
 }
@@ -1,10 +1,15 @@
-use crate::util::file_ex::FileEx;
+use crate::VERSION;
+use crate::library::cache::{HashingMethod, LibraryCache};
+use crate::util::file_ex::{self, FileEx};
 use crate::util::lockfile::{self, LockfileHandle};
+use crate::util::path::{PathError, to_library_relative};
 use crate::util::timestamp::NsTimestamp;
 use crate::util::uuid::UuidString;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process;
 use uuid::Uuid;
 
 /// Basic metadata about the file from the `stat` command.
@@ -98,10 +103,23 @@ pub enum ContentDescription {
     Other,
 }
 
+impl ContentDescription {
+    /// Which game is shown in the video or image, if it is known?
+    pub fn game_id(&self) -> Option<&GameId> {
+        match self {
+            Self::GameplayNormal { game } | Self::GameplayOnly { game } | Self::ResultsScreen { game } | Self::GameGeneric { game } => game.as_ref(),
+            Self::Unspecified | Self::Other => None,
+        }
+    }
+}
+
 /// The quality state of the proof file.
 ///
 /// Videos that are "raw" can be transcoded and lossily compressed to save space.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+///
+/// The variants are ordered from the least destructive to the most destructive, so that transcoding can only ever move a file
+/// further down the ladder (Raw → Compressed → Crumpled → Shredded). See [`LibraryEntry::can_transition_to`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum QualityState {
     /// Default value - value not selected by user yet.
@@ -125,8 +143,18 @@ pub enum QualityState {
     Shredded,
 }
 
+impl QualityState {
+    /// Can a file of this quality be processed into a file of the `target` quality? See [`LibraryEntry::can_transition_to`].
+    ///
+    /// [`QualityState::Unspecified`] files can never be processed, since the file could already be further down the ladder than the target
+    /// (for example, a shredded file that was scanned in and never had its quality set).
+    pub fn can_transition_to(self, target: QualityState) -> bool {
+        self != QualityState::Unspecified && target > self
+    }
+}
+
 /// Kind of the library entry - is it a proof of a play or something else?
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum LibraryEntryKind {
     /// Default value - value not selected by user yet.
@@ -215,13 +243,43 @@ pub struct LibraryEntry {
 
     /// Timestamp (in nanoseconds) of when this file was added/scanned into the library.
     pub timestamp_added: NsTimestamp,
+
+    /// ID of the YouTube video that this file was uploaded as.
+    ///
+    /// Set this to [`None`] if the file has not been uploaded to YouTube.
+    #[serde(default)]
+    pub youtube_video_id: Option<String>,
+
+    /// Timestamp (in nanoseconds) of when this file was uploaded to YouTube.
+    #[serde(default)]
+    pub upload_timestamp: Option<NsTimestamp>,
+
+    /// Does at least one of the [`Self::library_urls`] still point to an existing file? Updated by [`LibraryDatabase::verify_presence`].
+    ///
+    /// Entries are assumed to be present until checked, which is also the case for databases written before this field was introduced.
+    #[serde(default = "default_present")]
+    pub present: bool,
+}
+
+fn default_present() -> bool {
+    true
+}
+
+impl LibraryEntry {
+    /// Can this entry's file be processed into a file of the `target` quality?
+    ///
+    /// Processing is lossy, so the quality can only ever become more destructive - this returns `true` only if `target` is further down
+    /// the [`QualityState`] ladder than the current quality of this entry. Entries of [`QualityState::Unspecified`] quality can't be processed at all.
+    pub fn can_transition_to(&self, target: QualityState) -> bool {
+        self.quality.can_transition_to(target)
+    }
 }
 
 impl Default for LibraryEntry {
     fn default() -> Self {
         Self {
             // Explicitly set custom values
-            uuid: Uuid::new_v4().into(),
+            uuid: UuidString::new_v4(),
             timestamp_added: NsTimestamp::now(),
 
             // Default values for other fields
@@ -239,40 +297,509 @@ impl Default for LibraryEntry {
             clips: None,
             tags: HashSet::new(),
             comment: None,
+            youtube_video_id: None,
+            upload_timestamp: None,
+            present: true,
         }
     }
 }
 
+#[derive(Debug)]
+pub enum Error {
+    PathError(PathError),
+    FileExError(file_ex::Error),
+}
+
+impl From<PathError> for Error {
+    fn from(value: PathError) -> Self {
+        Self::PathError(value)
+    }
+}
+
+impl From<file_ex::Error> for Error {
+    fn from(value: file_ex::Error) -> Self {
+        Self::FileExError(value)
+    }
+}
+
+/// A file whose current hash is different from the hash stored in its library entry, see [`LibraryDatabase::verify_hashes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashMismatch {
+    /// UUID of the library entry.
+    pub uuid: UuidString,
+
+    /// Library URL of the mismatched file.
+    pub url: String,
+
+    /// Hash stored in the library entry.
+    pub expected: String,
+
+    /// Hash of the file on disk.
+    pub actual: String,
+}
+
+#[derive(Debug)]
+pub struct EntryNotFound;
+
+impl fmt::Display for EntryNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "library entry with this UUID was not found")
+    }
+}
+
+impl std::error::Error for EntryNotFound {}
+
+/// The library cache uses a hashing method other than [`HashingMethod::SHA256`], which cannot be compared with the hashes stored in library entries.
+#[derive(Debug)]
+pub struct UnsupportedHashingMethod(pub HashingMethod);
+
+impl fmt::Display for UnsupportedHashingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "library entries store SHA256 hashes, but the cache uses {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedHashingMethod {}
+
+/// Kind of change made to the library database.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    /// A new library entry was added.
+    Add,
+
+    /// A library entry was marked as uploaded to YouTube.
+    MarkUploaded,
+
+    /// A new library URL was added to an existing library entry.
+    AddUrl,
+
+    /// A library entry was replaced with an updated version.
+    Update,
+
+    /// A library entry was removed.
+    Remove,
+}
+
+/// A single record in the library database journal.
+///
+/// The journal is an append-only log of changes made to the library database, kept next to the database file.
+/// It is never read by the library itself, and exists only so that changes made by different processes can be audited later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    /// When was the change made?
+    pub timestamp: NsTimestamp,
+
+    /// PID of the process that made the change.
+    pub pid: u32,
+
+    /// Version of scoretracker that made the change.
+    pub version: String,
+
+    /// What kind of change was made?
+    pub operation: ChangeOperation,
+
+    /// UUID of the changed library entry.
+    pub uuid: UuidString,
+}
+
+/// A copy of all entries of a [`LibraryDatabase`] at some point in time.
+///
+/// See [`LibraryDatabase::snapshot`] and [`LibraryDatabase::restore`].
+#[derive(Debug, Clone)]
+pub struct LibrarySnapshot {
+    entries: Vec<LibraryEntry>,
+}
+
 #[derive(Debug)]
 pub struct LibraryDatabase {
     entries: Vec<LibraryEntry>,
     lockfile: LockfileHandle,
+    journal_path: Option<PathBuf>,
+
+    /// Journal records held back while [`Self::with_rollback_on_err`] is running.
+    ///
+    /// They are appended to the journal only once the outermost operation succeeds, and discarded if it is rolled back.
+    pending_journal: Option<Vec<ChangeRecord>>,
+
+    /// Map of 'SHA256 hash' : 'index of the first entry with that hash in [`Self::entries`]'.
+    ///
+    /// This is not saved to the database file - it is rebuilt whenever the entries are loaded or replaced.
+    sha256_index: HashMap<String, usize>,
+
+    /// Domain name used in new library URLs, see [`Self::library_url`].
+    domain_name: String,
 }
 
 impl LibraryDatabase {
     pub const STANDARD_FILENAME: &str = "library_database.json";
+    pub const JOURNAL_FILENAME: &str = "library_database.journal.jsonl";
+
+    /// Domain name used in library URLs until another one is set with [`Self::set_domain_name`].
+    pub const DEFAULT_DOMAIN_NAME: &str = "localhost";
+
+    /// Domain name of the library, used in new library URLs.
+    pub fn domain_name(&self) -> &str {
+        &self.domain_name
+    }
+
+    /// Set the domain name of the library, used in new library URLs.
+    ///
+    /// URLs of existing entries are not changed.
+    pub fn set_domain_name(&mut self, domain_name: String) {
+        self.domain_name = domain_name;
+    }
+
+    /// Start recording changes to this database in the journal file, see [`ChangeRecord`].
+    ///
+    /// The journal is stored next to the database file, and is called [`Self::JOURNAL_FILENAME`].
+    pub fn enable_journal(&mut self) {
+        let directory = self.lockfile.main_file_path().parent().unwrap_or(Path::new(""));
+        self.journal_path = Some(directory.join(Self::JOURNAL_FILENAME));
+    }
+
+    /// Stop recording changes to this database in the journal file.
+    pub fn disable_journal(&mut self) {
+        self.journal_path = None;
+    }
+
+    pub fn journal_path(&self) -> Option<&Path> {
+        self.journal_path.as_deref()
+    }
+
+    /// Append a record about a change to the journal, if journaling is enabled.
+    ///
+    /// While a [`Self::with_rollback_on_err`] operation is running, the record is held back until the operation finishes.
+    /// Failing to write to the journal does not undo the change - a warning is printed on stderr instead.
+    fn journal(&mut self, operation: ChangeOperation, uuid: Uuid) {
+        if self.journal_path.is_none() {
+            return;
+        }
+        let record = ChangeRecord {
+            timestamp: NsTimestamp::now(),
+            pid: process::id(),
+            version: VERSION.to_string(),
+            operation,
+            uuid: uuid.into(),
+        };
+        match &mut self.pending_journal {
+            Some(pending) => pending.push(record),
+            None => self.append_to_journal(&[record]),
+        }
+    }
+
+    /// Append records to the journal file, if journaling is enabled.
+    fn append_to_journal(&self, records: &[ChangeRecord]) {
+        let Some(journal_path) = &self.journal_path else {
+            return;
+        };
+        for record in records {
+            if let Err(e) = journal_path.append_jsonline(record) {
+                eprintln!("warning: could not write to library database journal at {journal_path:?}: {e:?}");
+                return;
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[LibraryEntry] {
+        &self.entries
+    }
+
+    /// Rebuild [`Self::sha256_index`] from scratch based on the current entries.
+    fn rebuild_sha256_index(&mut self) {
+        self.sha256_index.clear();
+        for (i, entry) in self.entries.iter().enumerate() {
+            self.sha256_index.entry(entry.sha256.clone()).or_insert(i);
+        }
+    }
+
+    /// Take a snapshot of the current entries, so that they can be restored later with [`Self::restore`].
+    pub fn snapshot(&self) -> LibrarySnapshot {
+        LibrarySnapshot {
+            entries: self.entries.clone(),
+        }
+    }
+
+    /// Replace all entries with the ones stored in the snapshot.
+    ///
+    /// Restoring is not recorded in the journal, and it doesn't write anything to disk on its own.
+    /// Changes made since the snapshot stay in the journal - use [`Self::with_rollback_on_err`] to keep undone changes out of it.
+    pub fn restore(&mut self, snapshot: LibrarySnapshot) {
+        self.entries = snapshot.entries;
+        self.rebuild_sha256_index();
+    }
+
+    /// Run a bulk operation on the database, rolling back all of its changes if it fails.
+    ///
+    /// A snapshot is taken before calling `f`. If `f` returns an Err variant, the snapshot is restored and the error is passed through.
+    /// Journal records of the changes made by `f` are held back while it runs - they are appended to the journal only if `f` succeeds,
+    /// so that a rolled back operation leaves no trace in the journal. Nested calls are rolled back independently,
+    /// and their records are appended once the outermost call succeeds.
+    pub fn with_rollback_on_err<T, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let snapshot = self.snapshot();
+        let outermost = self.pending_journal.is_none();
+        let pending_len = self.pending_journal.get_or_insert_with(Vec::new).len();
+
+        let result = f(self);
+
+        if result.is_err() {
+            self.restore(snapshot);
+            if let Some(pending) = &mut self.pending_journal {
+                pending.truncate(pending_len);
+            }
+        }
+        if outermost && let Some(pending) = self.pending_journal.take() {
+            self.append_to_journal(&pending);
+        }
+        result
+    }
+
+    pub fn get_entry(&self, uuid: Uuid) -> Option<&LibraryEntry> {
+        self.entries.iter().find(|entry| entry.uuid.0 == uuid)
+    }
+
+    /// Get a mutable reference to the entry with the given UUID.
+    ///
+    /// The SHA256 hash of the entry should not be changed through this reference, since the hash lookup table would go out of sync - use [`Self::update_entry`] for that.
+    /// Changes made through this reference are not recorded in the journal.
+    pub fn get_entry_mut(&mut self, uuid: Uuid) -> Option<&mut LibraryEntry> {
+        self.entries.iter_mut().find(|entry| entry.uuid.0 == uuid)
+    }
+
+    /// Replace the entry that has the same UUID as `entry` with `entry`.
+    ///
+    /// # Errors
+    /// If the database does not have an entry with this UUID, nothing happens and an Err variant is returned.
+    pub fn update_entry(&mut self, entry: LibraryEntry) -> Result<(), EntryNotFound> {
+        let uuid = entry.uuid.0;
+        let existing = self.get_entry_mut(uuid).ok_or(EntryNotFound)?;
+        let sha256_changed = existing.sha256 != entry.sha256;
+        *existing = entry;
+        if sha256_changed {
+            self.rebuild_sha256_index();
+        }
+        self.journal(ChangeOperation::Update, uuid);
+        Ok(())
+    }
+
+    /// Remove the entry with the given UUID from the database, returning it.
+    ///
+    /// Returns [`None`] if the database does not have an entry with this UUID.
+    pub fn remove_entry(&mut self, uuid: Uuid) -> Option<LibraryEntry> {
+        let i = self.entries.iter().position(|entry| entry.uuid.0 == uuid)?;
+        let entry = self.entries.remove(i);
+        self.rebuild_sha256_index();
+        self.journal(ChangeOperation::Remove, uuid);
+        Some(entry)
+    }
+
+    /// Record that the file of a library entry has been uploaded to YouTube as the video with the given ID.
+    ///
+    /// # Errors
+    /// If the database does not have an entry with this UUID, nothing happens and an Err variant is returned.
+    pub fn mark_uploaded(&mut self, uuid: Uuid, video_id: String) -> Result<(), EntryNotFound> {
+        let entry = self.get_entry_mut(uuid).ok_or(EntryNotFound)?;
+        entry.youtube_video_id = Some(video_id);
+        entry.upload_timestamp = Some(NsTimestamp::now());
+        self.journal(ChangeOperation::MarkUploaded, uuid);
+        Ok(())
+    }
+
+    /// Record another known location of the file of a library entry.
+    ///
+    /// Returns `true` if the URL was added, or `false` if the entry already listed this URL.
+    ///
+    /// # Errors
+    /// If the database does not have an entry with this UUID, nothing happens and an Err variant is returned.
+    pub fn add_url_to_entry(&mut self, uuid: Uuid, url: String) -> Result<bool, EntryNotFound> {
+        let entry = self.get_entry_mut(uuid).ok_or(EntryNotFound)?;
+        if entry.library_urls.contains(&url) {
+            return Ok(false);
+        }
+        entry.library_urls.push(url);
+        self.journal(ChangeOperation::AddUrl, uuid);
+        Ok(true)
+    }
+
+    /// Find entries of the given kind which have not been uploaded to YouTube yet.
+    pub fn not_yet_uploaded(&self, kind: LibraryEntryKind) -> Vec<&LibraryEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.entry_kind == kind && entry.youtube_video_id.is_none())
+            .collect()
+    }
+
+    /// Find entries that have the given tag assigned.
+    pub fn entries_with_tag(&self, tag: &str) -> Vec<&LibraryEntry> {
+        self.entries.iter().filter(|entry| entry.tags.contains(tag)).collect()
+    }
+
+    /// Find entries of the given kind.
+    pub fn entries_of_kind(&self, kind: LibraryEntryKind) -> Vec<&LibraryEntry> {
+        self.entries.iter().filter(|entry| entry.entry_kind == kind).collect()
+    }
+
+    /// Find entries showing the given game, see [`ContentDescription::game_id`].
+    pub fn entries_by_game(&self, game: &GameId) -> Vec<&LibraryEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.content_description.game_id() == Some(game))
+            .collect()
+    }
 
     pub fn find_entry_by_sha256_hash(&self, sha256: &str) -> Option<&LibraryEntry> {
-        self.entries.iter().find(|x| x.sha256 == sha256)
+        self.sha256_index.get(sha256).map(|&i| &self.entries[i])
+    }
+
+    /// Build the library URL of a file inside of the library directory, in the form of `stpl://<domain name>/<relative/path>`.
+    ///
+    /// # Errors
+    /// This function returns a [`PathError`] if the file's path relative to `library_dir` cannot be determined, see [`to_library_relative`].
+    /// This includes the case of the file not being inside of the library directory ([`PathError::OutsideOfRoot`]).
+    pub fn library_url(&self, library_dir: &Path, file_path: &Path) -> Result<String, PathError> {
+        let relative_file_path = to_library_relative(library_dir, file_path)?;
+        Ok(format!("stpl://{}/{relative_file_path}", self.domain_name))
+    }
+
+    /// Get the path on disk of a file referenced by a library URL.
+    ///
+    /// Returns [`None`] if the URL does not point to this library, which means that it has a different domain name, see [`Self::library_url`].
+    pub fn local_path(&self, library_dir: &Path, library_url: &str) -> Option<PathBuf> {
+        let relative_path = library_url.strip_prefix("stpl://")?.strip_prefix(self.domain_name.as_str())?.strip_prefix('/')?;
+        Some(library_dir.join(relative_path))
+    }
+
+    /// Check whether the files of library entries still exist, and update [`LibraryEntry::present`] accordingly.
+    ///
+    /// An entry is present if at least one of its library URLs that point to this library refers to an existing file.
+    /// Returns the UUIDs of entries that are not present.
+    pub fn verify_presence(&mut self, library_dir: &Path) -> Vec<UuidString> {
+        let mut missing = Vec::new();
+        for i in 0..self.entries.len() {
+            let present = self.entries[i]
+                .library_urls
+                .iter()
+                .filter_map(|library_url| self.local_path(library_dir, library_url))
+                .any(|path| path.is_file());
+            let entry = &mut self.entries[i];
+            entry.present = present;
+            if !present {
+                missing.push(entry.uuid);
+            }
+        }
+        missing
     }
 
-    pub fn add(&mut self, file_path: &Path, sha256: String) -> Uuid {
-        const DOMAIN: &str = "domain.example.com"; // TODO
-        let relative_file_path = file_path.to_string_lossy().to_string(); // TODO
+    /// Hash the files of all library entries again, and compare the results with the stored hashes.
+    ///
+    /// Cached hashes are not used, since they would hide any changes that kept the file's metadata intact, but the cache is updated with the new hashes
+    /// and written to disk once all files have been hashed. Failing to save the cache only prints a warning on stderr.
+    /// Files that cannot be read are skipped with a warning - see [`Self::verify_presence`] for finding missing files.
+    ///
+    /// # Errors
+    /// This function returns [`UnsupportedHashingMethod`] if the cache's [`LibraryCache::hashing_method`] is not [`HashingMethod::SHA256`],
+    /// since library entries always store SHA256 hashes.
+    pub fn verify_hashes(&self, library_dir: &Path, cache: &mut LibraryCache) -> Result<Vec<HashMismatch>, UnsupportedHashingMethod> {
+        if cache.hashing_method() != HashingMethod::SHA256 {
+            return Err(UnsupportedHashingMethod(cache.hashing_method()));
+        }
+        let mut mismatches = Vec::new();
+        for entry in &self.entries {
+            for library_url in &entry.library_urls {
+                let Some(path) = self.local_path(library_dir, library_url) else {
+                    continue;
+                };
+                let actual = match cache.recompute_file_hash(&path) {
+                    Ok(actual) => actual,
+                    Err(e) => {
+                        eprintln!("warning: could not verify hash of {path:?}: {e:?}");
+                        continue;
+                    }
+                };
+                if actual != entry.sha256 {
+                    mismatches.push(HashMismatch {
+                        uuid: entry.uuid,
+                        url: library_url.clone(),
+                        expected: entry.sha256.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+        if let Err(e) = cache.write_to_file() {
+            eprintln!("warning: could not save library cache after verifying hashes: {e:?}");
+        }
+        Ok(mismatches)
+    }
+
+    /// Add a new library entry for a file inside of the library directory.
+    ///
+    /// Duplicate files are the same library item - if an entry with the same SHA256 hash already exists,
+    /// the file's URL is added to that entry instead (see [`Self::add_url_to_entry`]) and the UUID of the existing entry is returned.
+    ///
+    /// # Errors
+    /// This function returns a [`PathError`] if the file's path relative to `library_dir` cannot be determined, see [`to_library_relative`].
+    pub fn add(&mut self, library_dir: &Path, file_path: &Path, sha256: String) -> Result<Uuid, PathError> {
         let library_entry = LibraryEntry {
-            library_urls: vec![format!("stpl://{DOMAIN}/{relative_file_path}")],
             sha256,
             ..Default::default()
         };
+        self.add_entry(library_dir, file_path, library_entry)
+    }
+
+    /// Add a prepared library entry for a file inside of the library directory.
+    ///
+    /// This is like [`Self::add`], except that the other fields of the entry (such as the quality or the source files) can be filled in beforehand.
+    /// The library URLs of the entry are replaced with the URL of `file_path`.
+    /// If an entry with the same SHA256 hash already exists, only the URL is added to it, and the rest of `library_entry` is discarded.
+    ///
+    /// # Errors
+    /// This function returns a [`PathError`] if the file's path relative to `library_dir` cannot be determined, see [`to_library_relative`].
+    pub fn add_entry(&mut self, library_dir: &Path, file_path: &Path, mut library_entry: LibraryEntry) -> Result<Uuid, PathError> {
+        let library_url = self.library_url(library_dir, file_path)?;
+        if let Some(uuid) = self.find_entry_by_sha256_hash(&library_entry.sha256).map(|entry| entry.uuid.0) {
+            self.add_url_to_entry(uuid, library_url).expect("entry should exist right after finding it");
+            return Ok(uuid);
+        }
+
+        library_entry.library_urls = vec![library_url];
         let uuid = library_entry.uuid.0;
+        self.sha256_index.insert(library_entry.sha256.clone(), self.entries.len());
         self.entries.push(library_entry);
-        uuid
+        self.journal(ChangeOperation::Add, uuid);
+        Ok(uuid)
+    }
+
+    /// Add a new library entry, and append it to the database file right away.
+    ///
+    /// This is like calling [`Self::add`] and then [`Self::write_to_file`], except that only the new entry is written,
+    /// instead of rewriting the whole database file.
+    /// If the file turns out to be a duplicate of an existing entry, the existing entry is modified, so the whole database file is rewritten after all.
+    pub fn add_and_append(&mut self, library_dir: &Path, file_path: &Path, sha256: String) -> Result<Uuid, Error> {
+        let is_duplicate = self.find_entry_by_sha256_hash(&sha256).is_some();
+        let uuid = self.add(library_dir, file_path, sha256)?;
+        if is_duplicate {
+            self.lockfile.write_as_jsonlines(&self.entries)?;
+        } else {
+            let entry = self.get_entry(uuid).expect("entry should exist right after adding it");
+            self.lockfile.append_jsonline(entry)?;
+        }
+        Ok(uuid)
     }
 
     pub fn read_or_create_new_safe<P: AsRef<Path>>(path: P) -> lockfile::Result<Self> {
         let lockfile = LockfileHandle::acquire_wait(path)?;
         let entries = lockfile.read_from_jsonlines()?.unwrap_or_default();
-        Ok(Self { entries, lockfile })
+        let mut database = Self {
+            entries,
+            lockfile,
+            journal_path: None,
+            pending_journal: None,
+            sha256_index: HashMap::new(),
+            domain_name: Self::DEFAULT_DOMAIN_NAME.to_string(),
+        };
+        database.rebuild_sha256_index();
+        Ok(database)
     }
 
     pub fn write_to_file(&self) -> lockfile::Result<()> {
@@ -1,12 +1,84 @@
 use crate::library::{cache::LibraryCache, database::LibraryDatabase};
-use crate::util::file_ex::{Error, FileEx};
+use crate::util::file_ex::{self, Error, FileEx};
+use crate::util::path::to_library_relative;
 use crate::util::uuid::UuidString;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::io;
 use std::path::PathBuf;
 use std::time::Instant;
+use std::collections::hash_map::Entry;
+use std::collections::HashSet;
 use std::{collections::HashMap, path::Path};
 use walkdir::WalkDir;
 
+/// Options controlling which files are picked up by [`LibraryIndex::scan_library_dir_with_options`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Extensions (without the leading dot) of files that should be scanned in. Compared case-insensitively.
+    pub scannable_extensions: Vec<String>,
+
+    /// Should symlinks inside of the library directory be followed?
+    ///
+    /// When enabled, every visited file and directory is canonicalized and remembered, so that symlink cycles are broken
+    /// and files reachable through multiple symlinks are only scanned once.
+    pub follow_symlinks: bool,
+}
+
+impl ScanOptions {
+    pub const DEFAULT_SCANNABLE_EXTENSIONS: [&str; 5] = ["mp4", "mkv", "png", "jpg", "webm"];
+
+    /// Returns whether a file with the given name has one of the [`Self::scannable_extensions`].
+    pub fn should_file_be_scanned(&self, filename: &str) -> bool {
+        let Some((_, extension)) = filename.rsplit_once('.') else {
+            return false;
+        };
+        self.scannable_extensions
+            .iter()
+            .any(|scannable_extension| scannable_extension.eq_ignore_ascii_case(extension))
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            scannable_extensions: Self::DEFAULT_SCANNABLE_EXTENSIONS.iter().map(|extension| extension.to_string()).collect(),
+            follow_symlinks: false,
+        }
+    }
+}
+
+/// Progress of a library scan, reported once for every file found in the library directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanProgress {
+    /// Index of the current file, starting at 0.
+    pub index: usize,
+
+    /// Total number of files found in the library directory, including the ones which are not going to be scanned in.
+    pub total: usize,
+
+    /// Path of the current file.
+    pub current_path: PathBuf,
+
+    /// Number of files skipped so far, not counting the current file.
+    pub skipped: usize,
+}
+
+/// Error encountered while scanning the library directory.
+///
+/// Only [`ScanError::CannotReadCache`] and [`ScanError::CannotWalkDir`] abort the whole scan.
+/// Files which cannot be read are skipped with a warning, described by [`ScanError::CannotReadFile`].
+#[derive(Debug)]
+pub enum ScanError {
+    /// The library cache file could not be read.
+    CannotReadCache(file_ex::Error),
+
+    /// The metadata or the contents of a file could not be read.
+    CannotReadFile(PathBuf, file_ex::Error),
+
+    /// The library directory could not be walked.
+    CannotWalkDir(walkdir::Error),
+}
+
 /// A mapping from paths to proof UUIDs.
 ///
 /// The library index is a data structure that links specific proof files on disk to proof UUIDs.
@@ -40,10 +112,10 @@ use walkdir::WalkDir;
 /// This data structure goes out of date whenever a new proof file gets added to the library,
 /// whenever a proof file gets moved around the library, and whenever a proof is removed from the library.
 /// To sync up the data structure again, the library needs to be *rescanned*.
-/// Scanning can be done via the [`LibraryIndex::scan_library_dir`] function, which returns
+/// Scanning can be done via the [`LibraryIndex::scan_library_dir`] function (or [`LibraryIndex::scan_library_dir_with_options`]), which returns
 /// an entirely new index structure, which can then be saved to disk.
 /// The saved file is usually called [`LibraryIndex::STANDARD_FILENAME`].
-#[derive(Debug, Clone, Serialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct LibraryIndex {
     // Map of 'relative file path' : 'proof UUID'
     pub files: HashMap<PathBuf, UuidString>,
@@ -51,58 +123,194 @@ pub struct LibraryIndex {
 
 impl LibraryIndex {
     pub const VERBOSE_SCANNING: bool = true;
+
+    /// Determines whether hashes of files missing from the cache are computed on multiple threads before the files are scanned in.
+    ///
+    /// If this is `false`, every file is hashed one by one on the scanning thread as it is encountered.
+    pub const PARALLEL_HASHING: bool = true;
     pub const STANDARD_FILENAME: &str = "library_index.json";
 
+    /// Returns whether a file with the given name is scanned in with the default [`ScanOptions`].
     pub fn should_file_be_scanned(filename: &str) -> bool {
-        filename.ends_with(".mp4") || filename.ends_with(".mkv")
+        ScanOptions::default().should_file_be_scanned(filename)
+    }
+
+    /// Scans the library directory using the default [`ScanOptions`].
+    pub fn scan_library_dir(library_dir: &Path, library_data: &mut LibraryDatabase) -> Result<Self, ScanError> {
+        Self::scan_library_dir_with_options(library_dir, library_data, &ScanOptions::default())
+    }
+
+    pub fn scan_library_dir_with_options(
+        library_dir: &Path,
+        library_data: &mut LibraryDatabase,
+        options: &ScanOptions,
+    ) -> Result<Self, ScanError> {
+        Self::scan(library_dir, library_data, options, None, &mut Self::print_progress)
+    }
+
+    /// Scans the library directory using the default [`ScanOptions`], reporting the progress through `on_progress` instead of printing it.
+    pub fn scan_library_dir_with_progress(
+        library_dir: &Path,
+        library_data: &mut LibraryDatabase,
+        mut on_progress: impl FnMut(ScanProgress),
+    ) -> Result<Self, ScanError> {
+        Self::scan(library_dir, library_data, &ScanOptions::default(), None, &mut on_progress)
+    }
+
+    /// Scans the library directory, reusing the results of a previous scan where possible.
+    ///
+    /// Paths present in `previous` which still point to a file with an up-to-date [`LibraryCache`] entry are assumed to be unchanged,
+    /// and keep their UUID from `previous` without being looked up in the library database. Only new or changed files are hashed.
+    /// Paths that no longer exist on disk are not included in the returned index.
+    pub fn scan_library_dir_incremental(
+        library_dir: &Path,
+        library_data: &mut LibraryDatabase,
+        previous: &LibraryIndex,
+    ) -> Result<Self, ScanError> {
+        Self::scan(library_dir, library_data, &ScanOptions::default(), Some(previous), &mut Self::print_progress)
+    }
+
+    fn print_progress(progress: ScanProgress) {
+        if LibraryIndex::VERBOSE_SCANNING {
+            println!("[scan] [{}/{}] {:?}", progress.index, progress.total, progress.current_path);
+        }
     }
 
-    pub fn scan_library_dir(library_dir: &Path, library_data: &mut LibraryDatabase) -> Self {
+    fn scan(
+        library_dir: &Path,
+        library_data: &mut LibraryDatabase,
+        options: &ScanOptions,
+        previous: Option<&LibraryIndex>,
+        on_progress: &mut dyn FnMut(ScanProgress),
+    ) -> Result<Self, ScanError> {
         let scanning_start_timestamp = Instant::now();
 
         let mut index = Self::default();
+        // a freshly read cache always uses SHA256, which is what library entries are keyed by
         let mut cache =
-            LibraryCache::read_or_create_new(library_dir.join(LibraryCache::STANDARD_FILENAME)).expect("could not read library cache");
-
-        let files_to_scan: Vec<_> = WalkDir::new(library_dir)
-            .into_iter()
-            .filter_map(|result| {
-                result
-                    .ok()
-                    .and_then(|dir_entry| if dir_entry.file_type().is_file() { Some(dir_entry) } else { None })
-            })
-            .collect();
+            LibraryCache::read_or_create_new(library_dir.join(LibraryCache::STANDARD_FILENAME)).map_err(ScanError::CannotReadCache)?;
+
+        let mut files_to_scan = Vec::new();
+        let mut visited = HashSet::new();
+        let mut walker = WalkDir::new(library_dir).follow_links(options.follow_symlinks).into_iter();
+        while let Some(result) = walker.next() {
+            match result {
+                Ok(dir_entry) => {
+                    if options.follow_symlinks {
+                        let canonical_path = match dir_entry.path().canonicalize() {
+                            Ok(canonical_path) => canonical_path,
+                            Err(e) => {
+                                eprintln!("warning: skipping {:?}, could not canonicalize it: {e:?}", dir_entry.path());
+                                continue;
+                            }
+                        };
+                        if !visited.insert(canonical_path) {
+                            if LibraryIndex::VERBOSE_SCANNING {
+                                println!("[scan] already visited {:?}, skipping", dir_entry.path());
+                            }
+                            if dir_entry.file_type().is_dir() {
+                                walker.skip_current_dir();
+                            }
+                            continue;
+                        }
+                    }
+                    if dir_entry.file_type().is_file() {
+                        files_to_scan.push(dir_entry);
+                    }
+                }
+                // the library directory itself could not be read, so there is nothing to scan
+                Err(e) if e.depth() == 0 => return Err(ScanError::CannotWalkDir(e)),
+                Err(e) => eprintln!("warning: skipping part of the library, could not walk it: {e:?}"),
+            }
+        }
         let len = files_to_scan.len();
         let mut skipped = 0;
 
+        // this has to be decided before any hashing, since hashing a changed file makes it look cached
+        let reusable_uuids: HashMap<&Path, UuidString> = match previous {
+            Some(previous) => files_to_scan
+                .iter()
+                .map(|dir_entry| dir_entry.path())
+                .filter_map(|path| {
+                    let relative_path = to_library_relative(library_dir, path).ok()?;
+                    let &uuid = previous.files.get(Path::new(&relative_path))?;
+                    cache.is_file_cached(path).then_some((path, uuid))
+                })
+                .collect(),
+            None => HashMap::new(),
+        };
+
+        if Self::PARALLEL_HASHING {
+            let paths: Vec<_> = files_to_scan
+                .iter()
+                .filter(|dir_entry| options.should_file_be_scanned(dir_entry.file_name().to_string_lossy().as_ref()))
+                .map(|dir_entry| dir_entry.path())
+                .filter(|path| !reusable_uuids.contains_key(path))
+                .collect();
+            if let Err(e) = cache.compute_missing_hashes_parallel(&paths) {
+                eprintln!("warning: could not save library cache after computing hashes: {e:?}");
+            }
+        }
+
         for (i, dir_entry) in files_to_scan.iter().enumerate() {
             let path = dir_entry.path();
+            on_progress(ScanProgress {
+                index: i,
+                total: len,
+                current_path: path.to_path_buf(),
+                skipped,
+            });
 
-            let is_supposed_to_be_scanned = Self::should_file_be_scanned(dir_entry.file_name().to_os_string().to_string_lossy().as_ref());
+            let is_supposed_to_be_scanned = options.should_file_be_scanned(dir_entry.file_name().to_string_lossy().as_ref());
             if !is_supposed_to_be_scanned {
                 if LibraryIndex::VERBOSE_SCANNING {
-                    println!("[scan] [{i}/{len}] skipping {path:?}");
+                    println!("[scan] skipping {path:?}");
                 }
                 skipped += 1;
                 continue;
             }
 
-            if LibraryIndex::VERBOSE_SCANNING {
-                println!("[scan] [{i}/{len}] scanning {path:?}");
-            }
+            let relative_path = match to_library_relative(library_dir, path) {
+                Ok(relative_path) => relative_path,
+                Err(e) => {
+                    eprintln!("warning: skipping {path:?}, could not get its path relative to the library: {e:?}");
+                    skipped += 1;
+                    continue;
+                }
+            };
 
-            let sha256_hash = cache.find_or_compute_file_sha256_hash(path);
-            let uuid = if let Some(entry) = library_data.find_entry_by_sha256_hash(&sha256_hash) {
-                let uuid = entry.uuid.0;
+            if let Some(&uuid) = reusable_uuids.get(path) {
                 if LibraryIndex::VERBOSE_SCANNING {
-                    println!("[scan] found duplicate file: sha256: {sha256_hash}, uuid: {uuid}");
+                    println!("[scan] reusing previous index entry for {path:?}: {uuid:?}");
+                }
+                index.files.insert(PathBuf::from(relative_path), uuid);
+                continue;
+            }
+
+            let sha256_hash = match cache.find_or_compute_file_sha256_hash(path) {
+                Ok(sha256_hash) => sha256_hash,
+                Err(e) => {
+                    let e = ScanError::CannotReadFile(path.to_path_buf(), e);
+                    eprintln!("warning: skipping {path:?}, could not get its hash: {e:?}");
+                    skipped += 1;
+                    continue;
+                }
+            };
+            if LibraryIndex::VERBOSE_SCANNING
+                && let Some(entry) = library_data.find_entry_by_sha256_hash(&sha256_hash)
+            {
+                println!("[scan] found duplicate file: sha256: {sha256_hash}, uuid: {}", entry.uuid.0);
+            }
+            // duplicate files are merged into the existing entry by `add`
+            let uuid = match library_data.add(library_dir, path, sha256_hash) {
+                Ok(uuid) => uuid,
+                Err(e) => {
+                    eprintln!("warning: skipping {path:?}, could not add it to the library database: {e:?}");
+                    skipped += 1;
+                    continue;
                 }
-                // TODO: record this duplicate file path in the library entry
-                uuid
-            } else {
-                library_data.add(path, sha256_hash)
             };
-            index.files.insert(path.to_owned(), uuid.into());
+            index.files.insert(PathBuf::from(relative_path), uuid.into());
         }
 
         let scanning_end_timestamp = Instant::now();
@@ -113,7 +321,36 @@ impl LibraryIndex {
             len - skipped
         );
 
-        index
+        Ok(index)
+    }
+
+    /// Merge another index into this one.
+    ///
+    /// This is meant for combining indices of multiple library directories into one.
+    /// Paths present in both indices are not overwritten - the entry from `self` is kept, and the path is returned in the list of collisions.
+    /// Since index paths are relative to their library directory, collisions across roots usually indicate a configuration problem,
+    /// such as the same directory being configured twice.
+    pub fn merge(&mut self, other: LibraryIndex) -> Vec<PathBuf> {
+        let mut collisions = Vec::new();
+        for (path, uuid) in other.files {
+            match self.files.entry(path) {
+                Entry::Occupied(entry) => collisions.push(entry.key().clone()),
+                Entry::Vacant(entry) => {
+                    entry.insert(uuid);
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Loads an index previously written with [`Self::save`].
+    ///
+    /// # Errors
+    /// Unlike most `read_or_create_new` style functions, a missing file is an error here ([`Error::CannotReadFile`] with [`io::ErrorKind::NotFound`]),
+    /// since an empty index would make the library look empty instead of out of date.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        path.read_from_json()?
+            .ok_or_else(|| Error::CannotReadFile(io::Error::from(io::ErrorKind::NotFound)))
     }
 
     pub fn save(&self, path: &Path) -> Result<(), Error> {
@@ -1,16 +1,122 @@
+use crate::util::file_ex::FileEx;
+use crate::util::lockfile::{self, LockfileHandle};
 use crate::util::{timestamp::NsTimestamp, uuid::UuidString};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::Path;
 
-// TODO
+/// Inner structure for [`PlayDatabase`].
+///
+/// This structure contains data that is actually deserialized/serialized into the play database file.
 #[derive(Deserialize, Serialize)]
-pub struct PlayDatabase {
+pub struct PlayDatabaseInner {
     pub format_version: i32,
     pub plays: Vec<Box<dyn PlayTrait>>,
 }
 
+impl Default for PlayDatabaseInner {
+    fn default() -> Self {
+        Self {
+            format_version: PlayDatabase::FORMAT_VERSION,
+            plays: Vec::new(),
+        }
+    }
+}
+
+/// Database of all recorded plays, of any game.
+///
+/// This is a wrapper structure for [`PlayDatabaseInner`], which keeps the database file locked for as long as it exists.
+/// Plays of different games are told apart by the `type` tag of [`PlayTrait`].
+pub struct PlayDatabase {
+    inner: PlayDatabaseInner,
+    lockfile: LockfileHandle,
+}
+
+impl PlayDatabase {
+    /// Standard filename used for the play database.
+    pub const STANDARD_FILENAME: &str = "play_database.json";
+
+    /// Format version written into newly created play databases.
+    pub const FORMAT_VERSION: i32 = 1;
+
+    pub fn format_version(&self) -> i32 {
+        self.inner.format_version
+    }
+
+    pub fn plays(&self) -> &[Box<dyn PlayTrait>] {
+        &self.inner.plays
+    }
+
+    pub fn add_play(&mut self, play: Box<dyn PlayTrait>) {
+        self.inner.plays.push(play);
+    }
+
+    pub fn read_or_create_new_safe<P: AsRef<Path>>(path: P) -> lockfile::Result<Self> {
+        let lockfile = LockfileHandle::acquire_wait(path)?;
+        let inner = lockfile.read_from_json()?.unwrap_or_default();
+        Ok(Self { inner, lockfile })
+    }
+
+    pub fn write_to_file(&self) -> lockfile::Result<()> {
+        Ok(self.lockfile.write_as_json_pretty(&self.inner)?)
+    }
+}
+
+/// Identifies a chart - a song played on a specific instrument and difficulty.
+///
+/// All fields are in the same format as returned by the corresponding [`PlayTrait`] methods.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChartKey {
+    pub song_id: String,
+    pub instrument: String,
+    pub difficulty: String,
+}
+
+/// Find the highest-scoring play of every chart.
+///
+/// If multiple plays share the highest score of a chart, the one that comes first in `plays` is picked.
+pub fn best_per_chart(plays: &[Box<dyn PlayTrait>]) -> HashMap<ChartKey, &dyn PlayTrait> {
+    let mut best: HashMap<ChartKey, &dyn PlayTrait> = HashMap::new();
+    for play in plays {
+        match best.entry(play.chart_key()) {
+            Entry::Occupied(mut entry) => {
+                if play.score() > entry.get().score() {
+                    entry.insert(play.as_ref());
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(play.as_ref());
+            }
+        }
+    }
+    best
+}
+
 #[typetag::serde(tag = "type")]
 pub trait PlayTrait {
     fn proof(&self) -> Vec<UuidString>;
     fn timestamp(&self) -> NsTimestamp;
     fn comment(&self) -> String;
+
+    /// Score achieved in the play, used to rank plays against each other.
+    fn score(&self) -> u64;
+
+    /// ID of the song that was played.
+    fn song_id(&self) -> String;
+
+    /// Name of the instrument that the song was played on, in the same format as used in the game's serialized data.
+    fn instrument(&self) -> String;
+
+    /// Name of the difficulty that the song was played on, in the same format as used in the game's serialized data.
+    fn difficulty(&self) -> String;
+
+    /// The chart that was played, see [`ChartKey`].
+    fn chart_key(&self) -> ChartKey {
+        ChartKey {
+            song_id: self.song_id(),
+            instrument: self.instrument(),
+            difficulty: self.difficulty(),
+        }
+    }
 }
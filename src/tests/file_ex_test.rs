@@ -0,0 +1,92 @@
+use crate::util::file_ex::{FileEx, atomic_temp_path_for};
+use crate::util::lockfile::LockfileHandle;
+use std::fs;
+
+#[test]
+fn interrupted_atomic_write_leaves_original_intact() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    path.write_as_json_atomic(vec![1, 2, 3]).unwrap();
+
+    // Simulate a write that died before the rename.
+    let temp_path = atomic_temp_path_for(&path).unwrap();
+    fs::write(&temp_path, "[4, 5").unwrap();
+    let original: Vec<i32> = path.read_from_json().unwrap().unwrap();
+    assert_eq!(original, vec![1, 2, 3]);
+
+    path.write_as_json_atomic(vec![4, 5, 6]).unwrap();
+    let updated: Vec<i32> = path.read_from_json().unwrap().unwrap();
+    assert_eq!(updated, vec![4, 5, 6]);
+    assert!(!temp_path.exists());
+}
+
+#[test]
+fn atomic_jsonlines_match_regular_jsonlines() {
+    let dir = tempfile::tempdir().unwrap();
+    let regular_path = dir.path().join("regular.jsonl");
+    let locked_path = dir.path().join("locked.jsonl");
+    let records = vec![("a", 1), ("b", 2)];
+
+    regular_path.write_as_jsonlines(&records).unwrap();
+    LockfileHandle::acquire(&locked_path).unwrap().write_as_jsonlines(&records).unwrap();
+
+    assert_eq!(fs::read(&regular_path).unwrap(), fs::read(&locked_path).unwrap());
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct SmallConfig {
+    domain_name: String,
+    library_dirs: Vec<String>,
+    verbose: bool,
+}
+
+#[test]
+fn toml_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    let config = SmallConfig {
+        domain_name: "example.com".to_string(),
+        library_dirs: vec!["/a".to_string(), "/b".to_string()],
+        verbose: true,
+    };
+
+    path.write_as_toml(&config).unwrap();
+    assert!(fs::read_to_string(&path).unwrap().contains("domain_name = \"example.com\""));
+    let loaded: SmallConfig = path.read_from_toml().unwrap().unwrap();
+    assert_eq!(loaded, config);
+}
+
+#[test]
+fn gzipped_jsonlines_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let records: Vec<(String, u64)> = (0..100).map(|i| (format!("record {i}"), i)).collect();
+
+    let gz_path = dir.path().join("records.jsonl.gz");
+    gz_path.write_as_jsonlines_gz(&records).unwrap();
+    assert_eq!(fs::read(&gz_path).unwrap()[..2], [0x1f, 0x8b]);
+    let loaded: Vec<(String, u64)> = gz_path.read_from_jsonlines_maybe_gz().unwrap().unwrap();
+    assert_eq!(loaded, records);
+
+    // Compressed files are detected by their contents too, not just by the extension.
+    let renamed_path = dir.path().join("records.jsonl");
+    fs::rename(&gz_path, &renamed_path).unwrap();
+    let loaded: Vec<(String, u64)> = renamed_path.read_from_jsonlines_maybe_gz().unwrap().unwrap();
+    assert_eq!(loaded, records);
+
+    let plain_path = dir.path().join("plain.jsonl");
+    plain_path.write_as_jsonlines(&records).unwrap();
+    let loaded: Vec<(String, u64)> = plain_path.read_from_jsonlines_maybe_gz().unwrap().unwrap();
+    assert_eq!(loaded, records);
+}
+
+#[test]
+fn lenient_jsonlines_skips_malformed_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tasks.jsonl");
+    fs::write(&path, "[\"a\",1]\n{garbage\n\n[\"b\",2]\n").unwrap();
+
+    assert!(path.read_from_jsonlines::<(String, u64)>().is_err());
+    let (records, malformed_lines) = path.read_from_jsonlines_lenient::<(String, u64)>().unwrap().unwrap();
+    assert_eq!(records, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+    assert_eq!(malformed_lines, vec![(2, "{garbage".to_string())]);
+}
@@ -0,0 +1,381 @@
+use crate::hive::job::{
+    CutRange, Error, Job, JobProgress, ProcessingType, Success, catch_job_panic, parse_ffmpeg_progress_line, probe_duration, run_on_thread_with_deadline,
+};
+use crate::hive::queue::TaskQueue;
+use crate::hive::task::{Task, TaskState};
+use crate::library::cache::{HashingMethod, LibraryCache, compute_hash_of_file};
+use crate::library::database::{LibraryDatabase, LibraryEntry, QualityState};
+use crate::library::index::LibraryIndex;
+use crate::util::uuid::UuidString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn entry_of_quality(quality: QualityState) -> LibraryEntry {
+    LibraryEntry {
+        quality,
+        ..Default::default()
+    }
+}
+
+fn process_video_job(source: &LibraryEntry, processing_type: ProcessingType) -> Result<Job, Error> {
+    Job::process_video(source, PathBuf::from("source.mkv"), processing_type, PathBuf::from("destination.mp4"))
+}
+
+#[test]
+fn process_video_accepts_quality_downgrade() {
+    let source = entry_of_quality(QualityState::Raw);
+    assert!(source.can_transition_to(QualityState::Crumpled));
+    let job = process_video_job(&source, ProcessingType::CompressCrumpleVideo).unwrap();
+    assert!(job.check_quality_transition().is_ok());
+    let Job::ProcessVideo {
+        source_proof_uuid,
+        source_quality,
+        ..
+    } = job
+    else {
+        panic!("expected a process_video job");
+    };
+    assert_eq!(source_proof_uuid, source.uuid);
+    assert_eq!(source_quality, QualityState::Raw);
+}
+
+#[test]
+fn process_video_refuses_quality_upgrade() {
+    let source = entry_of_quality(QualityState::Shredded);
+    assert!(!source.can_transition_to(QualityState::Compressed));
+    let result = process_video_job(&source, ProcessingType::CompressImportantVideo);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidQualityTransition {
+            from: QualityState::Shredded,
+            to: QualityState::Compressed
+        })
+    ));
+}
+
+#[test]
+fn process_video_refuses_unspecified_quality() {
+    let source = entry_of_quality(QualityState::Unspecified);
+    for processing_type in [ProcessingType::CompressImportantVideo, ProcessingType::CompressCrumpleVideo, ProcessingType::CompressShredVideo] {
+        assert!(!source.can_transition_to(processing_type.target_quality()));
+        assert!(matches!(
+            process_video_job(&source, processing_type),
+            Err(Error::InvalidQualityTransition {
+                from: QualityState::Unspecified,
+                ..
+            })
+        ));
+    }
+}
+
+#[test]
+fn running_process_video_refuses_quality_upgrade() {
+    let dir = tempfile::tempdir().unwrap();
+    let destination_path = dir.path().join("destination.mp4");
+    let job = Job::ProcessVideo {
+        source_proof_uuid: UuidString::new_v4(),
+        source_path: dir.path().join("source.mkv"),
+        source_quality: QualityState::Crumpled,
+        processing_type: ProcessingType::CompressImportantVideo,
+        destination_path: destination_path.clone(),
+    };
+    assert!(matches!(
+        job.run(None),
+        Err(Error::InvalidQualityTransition {
+            from: QualityState::Crumpled,
+            to: QualityState::Compressed
+        })
+    ));
+    assert!(!destination_path.exists());
+}
+
+#[test]
+fn cut_range_open_ended() {
+    let range = CutRange::new(Some(1_500), None).unwrap();
+    assert_eq!(range.start_ms, Some(1_500));
+    assert_eq!(range.end_ms, None);
+    assert!(CutRange::new(None, None).is_ok());
+}
+
+#[test]
+fn cut_range_fully_specified() {
+    let range = CutRange::new(Some(1_500), Some(90_000)).unwrap();
+    assert_eq!(range, CutRange { start_ms: Some(1_500), end_ms: Some(90_000) });
+}
+
+#[test]
+fn cut_range_reversed_is_rejected() {
+    let result = CutRange::new(Some(90_000), Some(1_500));
+    assert!(matches!(result, Err(Error::InvalidCutRange { start_ms: 90_000, end_ms: 1_500 })));
+}
+
+#[test]
+fn cut_range_ffmpeg_args() {
+    assert_eq!(CutRange::new(None, None).unwrap().ffmpeg_input_args(), Vec::<String>::new());
+    assert_eq!(CutRange::new(Some(1500), None).unwrap().ffmpeg_input_args(), ["-ss", "1.500"]);
+    assert_eq!(CutRange::new(Some(250), Some(62_005)).unwrap().ffmpeg_input_args(), ["-ss", "0.250", "-to", "62.005"]);
+}
+
+#[test]
+fn cut_video_deserializes_old_field_names() {
+    let json = r#"{"type":"cut_video","details":{"source_proof_uuid":"67e55044-10b1-426f-9247-bb680e5fe0c8","source_path":"a.mkv","cut_point_start_ms":100,"cut_point_end_ms":200,"destination_path":"b.mkv"}}"#;
+    let job: Job = serde_json::from_str(json).unwrap();
+    let Job::CutVideo { cut_range, .. } = job else {
+        panic!("expected a cut_video job");
+    };
+    assert_eq!(cut_range, CutRange { start_ms: Some(100), end_ms: Some(200) });
+}
+
+#[test]
+fn sleep_job_observes_cancellation() {
+    let cancelled = AtomicBool::new(false);
+    let job = Job::Sleep {
+        time_nanos: Duration::from_secs(30).as_nanos() as i128,
+    };
+    let start = Instant::now();
+    let result = thread::scope(|scope| {
+        let handle = scope.spawn(|| job.run_cancellable(&cancelled, None));
+        thread::sleep(Duration::from_millis(50));
+        cancelled.store(true, Ordering::Relaxed);
+        handle.join().unwrap()
+    });
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn processed_video_is_recorded_in_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let destination_path = dir.path().join("crumpled.mp4");
+    fs::write(&destination_path, "crumpled video").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let dry = UuidString::new_v4();
+    let wet = UuidString::new_v4();
+    let success = Success::ProcessedVideo {
+        dry,
+        wet,
+        destination_path,
+        quality: QualityState::Crumpled,
+    };
+
+    let uuid = success.record_in_library(dir.path(), &mut database).unwrap();
+
+    assert_eq!(uuid, Some(wet.0));
+    let entry = database.get_entry(wet.0).unwrap();
+    assert_eq!(entry.dry, Some(dry));
+    assert_eq!(entry.quality, QualityState::Crumpled);
+    assert!(entry.library_urls[0].ends_with("/crumpled.mp4"));
+    assert_eq!(Success::Finished.record_in_library(dir.path(), &mut database).unwrap(), None);
+}
+
+/// Generate a losslessly encoded, 2 seconds long 720p test pattern video.
+fn generate_test_clip(path: &Path) {
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-f", "lavfi", "-i", "testsrc=duration=2:size=1280x720:rate=30"])
+        .args(["-c:v", "libx264", "-qp", "0"])
+        .arg(path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+#[ignore = "requires ffmpeg and ffprobe, run with `cargo test -- --ignored`"]
+fn process_video_compresses_with_ffmpeg() {
+    let dir = tempfile::tempdir().unwrap();
+    let source_path = dir.path().join("source.mkv");
+    generate_test_clip(&source_path);
+    let source_size = fs::metadata(&source_path).unwrap().len();
+
+    for processing_type in [ProcessingType::CompressImportantVideo, ProcessingType::CompressCrumpleVideo, ProcessingType::CompressShredVideo] {
+        let destination_path = dir.path().join(format!("{:?}.mp4", processing_type.target_quality()));
+        let job = Job::ProcessVideo {
+            source_proof_uuid: UuidString::new_v4(),
+            source_path: source_path.clone(),
+            source_quality: QualityState::Raw,
+            processing_type,
+            destination_path: destination_path.clone(),
+        };
+        let Success::ProcessedVideo { quality, .. } = job.run(None).unwrap() else {
+            panic!("expected a processed video");
+        };
+        assert_eq!(quality, processing_type.target_quality());
+        assert!(fs::metadata(&destination_path).unwrap().len() < source_size);
+    }
+}
+
+#[test]
+#[ignore = "requires ffmpeg and ffprobe, run with `cargo test -- --ignored`"]
+fn process_video_reports_ffmpeg_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let job = Job::ProcessVideo {
+        source_proof_uuid: UuidString::new_v4(),
+        source_path: dir.path().join("missing.mkv"),
+        source_quality: QualityState::Raw,
+        processing_type: ProcessingType::CompressShredVideo,
+        destination_path: dir.path().join("output.mp4"),
+    };
+    assert!(matches!(job.run(None), Err(Error::FfmpegFailed { .. })));
+}
+
+#[test]
+fn panic_on_deadline_thread_returns_error() {
+    let result = run_on_thread_with_deadline(Duration::from_secs(5), None, |_, _| panic!("bad job"));
+    assert!(matches!(result, Err(Error::JobPanicked(message)) if message == "bad job"));
+}
+
+#[test]
+fn panic_on_current_thread_returns_error() {
+    let result = catch_job_panic(|| panic!("bad job"));
+    assert!(matches!(result, Err(Error::JobPanicked(message)) if message == "bad job"));
+}
+
+#[test]
+fn deadline_thread_gives_up_after_deadline() {
+    let start = Instant::now();
+    let result = run_on_thread_with_deadline(Duration::from_millis(20), None, |cancelled, _| {
+        while !cancelled.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        Err(Error::Cancelled)
+    });
+    assert!(matches!(result, Err(Error::TimedOut)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn sleep_job_reports_progress() {
+    let job = Job::Sleep {
+        time_nanos: Duration::from_millis(20).as_nanos() as i128,
+    };
+    let mut events: Vec<JobProgress> = Vec::new();
+    job.run(Some(&mut |progress| events.push(progress))).unwrap();
+
+    assert_eq!(events.first().unwrap().percent, 0.0);
+    assert_eq!(events.last().unwrap().percent, 100.0);
+}
+
+#[test]
+fn progress_is_forwarded_from_deadline_thread() {
+    let job = Job::Sleep {
+        time_nanos: Duration::from_millis(20).as_nanos() as i128,
+    };
+    let mut events: Vec<JobProgress> = Vec::new();
+    job.run_with_deadline(Duration::from_secs(5), Some(&mut |progress| events.push(progress))).unwrap();
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events.last().unwrap().percent, 100.0);
+}
+
+#[test]
+fn ffmpeg_progress_lines_are_parsed() {
+    let duration = Duration::from_secs(10);
+    assert_eq!(parse_ffmpeg_progress_line("out_time_us=2500000", duration), Some(25.0));
+    assert_eq!(parse_ffmpeg_progress_line("out_time_us=12000000", duration), Some(100.0));
+    assert_eq!(parse_ffmpeg_progress_line("out_time_us=N/A", duration), None);
+    assert_eq!(parse_ffmpeg_progress_line("out_time_ms=2500000", duration), None);
+    assert_eq!(parse_ffmpeg_progress_line("progress=continue", duration), None);
+    assert_eq!(parse_ffmpeg_progress_line("out_time_us=100", Duration::ZERO), None);
+}
+
+#[test]
+fn compute_hash_task_returns_file_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dropped.mkv");
+    fs::write(&path, "newly dropped file").unwrap();
+    let mut queue = TaskQueue::read_or_create_new_safe(dir.path().join(TaskQueue::STANDARD_FILENAME)).unwrap();
+    let task = Task::new("hash".to_string(), Job::ComputeHash { path: path.clone() });
+    queue.add_task(task).unwrap();
+
+    let task = queue.top_eligible_task_mut().unwrap();
+    task.execute();
+
+    assert!(task.state == TaskState::Done);
+    let success: Success = serde_json::from_value(task.results.clone().unwrap()).unwrap();
+    let expected = compute_hash_of_file(&path, HashingMethod::SHA256).unwrap();
+    assert_eq!(success, Success::Hash { path: path.clone(), sha256: expected.clone() });
+
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert!(success.record_in_cache(&mut cache).unwrap());
+    assert_eq!(cache.find_or_compute_file_sha256_hash(&path).unwrap(), expected);
+    assert_eq!(cache.entries().len(), 1);
+}
+
+#[test]
+fn scan_library_task_reports_counts() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    fs::write(dir.path().join("first.mkv"), "first").unwrap();
+    fs::write(dir.path().join("nested").join("second.mp4"), "second").unwrap();
+    fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+    let queue_dir = tempfile::tempdir().unwrap();
+    let mut queue = TaskQueue::read_or_create_new_safe(queue_dir.path().join(TaskQueue::STANDARD_FILENAME)).unwrap();
+    let job = Job::ScanLibrary {
+        library_dir: dir.path().to_path_buf(),
+    };
+    queue.add_task(Task::new("scan".to_string(), job)).unwrap();
+
+    let task = queue.top_eligible_task_mut().unwrap();
+    task.execute();
+
+    assert!(task.state == TaskState::Done);
+    let success: Success = serde_json::from_value(task.results.clone().unwrap()).unwrap();
+    let Success::Scanned { files_scanned, skipped, .. } = success else {
+        panic!("expected a scan result");
+    };
+    assert_eq!(files_scanned, 2);
+    // the text file and the lockfile of the library database
+    assert_eq!(skipped, 2);
+
+    // the database lock is released after the scan
+    let database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    assert_eq!(database.entries().len(), 2);
+    let index = LibraryIndex::load(&dir.path().join(LibraryIndex::STANDARD_FILENAME)).unwrap();
+    assert_eq!(index.files.len(), 2);
+}
+
+#[test]
+#[ignore = "requires ffmpeg and ffprobe, run with `cargo test -- --ignored`"]
+fn generate_thumbnail_with_ffmpeg() {
+    let dir = tempfile::tempdir().unwrap();
+    let source_path = dir.path().join("source.mkv");
+    generate_test_clip(&source_path);
+    let proof = UuidString::new_v4();
+
+    // the second timestamp is past the end of the clip, so the last frame is used instead
+    for (timestamp_ms, filename) in [(500, "thumbnail.png"), (60_000, "past_end.jpg")] {
+        let destination_path = dir.path().join(filename);
+        let job = Job::GenerateThumbnail {
+            source_proof_uuid: proof,
+            source_path: source_path.clone(),
+            timestamp_ms,
+            destination_path: destination_path.clone(),
+        };
+        assert_eq!(job.run(None).unwrap(), Success::Thumbnail { proof, path: destination_path.clone() });
+        assert!(fs::metadata(&destination_path).unwrap().len() > 0);
+    }
+}
+
+#[test]
+#[ignore = "requires ffmpeg and ffprobe, run with `cargo test -- --ignored`"]
+fn cut_video_with_ffmpeg() {
+    let dir = tempfile::tempdir().unwrap();
+    let source_path = dir.path().join("source.mkv");
+    generate_test_clip(&source_path);
+    let destination_path = dir.path().join("cut.mkv");
+    let proof = UuidString::new_v4();
+    let job = Job::CutVideo {
+        source_proof_uuid: proof,
+        source_path: source_path.clone(),
+        cut_range: CutRange::new(None, Some(1000)).unwrap(),
+        destination_path: destination_path.clone(),
+    };
+
+    assert_eq!(job.run(None).unwrap(), Success::Cut { proof, path: destination_path.clone() });
+    let cut_duration = probe_duration(&destination_path).unwrap();
+    assert!(cut_duration < probe_duration(&source_path).unwrap());
+}
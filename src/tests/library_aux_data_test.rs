@@ -0,0 +1,99 @@
+use crate::library::aux_data::{Color, LibraryAuxData, TagInfo};
+use std::fs;
+
+#[test]
+fn hex_color_round_trip() {
+    let color = Color::from_hex("#1a2b3c").unwrap();
+    assert_eq!(color, Color::new(0x1a, 0x2b, 0x3c));
+    assert_eq!(color.to_hex(), "#1a2b3c");
+    assert_eq!(Color::from_hex("#FFAA00").unwrap().to_hex(), "#ffaa00");
+    assert_eq!(Color::from_hex(&Color::DEFAULT.to_hex()).unwrap(), Color::default());
+}
+
+#[test]
+fn invalid_hex_colors_are_rejected() {
+    assert!(Color::from_hex("#abc").is_err());
+    assert!(Color::from_hex("aabbcc").is_err());
+    assert!(Color::from_hex("#aabbc").is_err());
+    assert!(Color::from_hex("#aabbccd").is_err());
+    assert!(Color::from_hex("#gg0000").is_err());
+    assert!(Color::from_hex("#+a0000").is_err());
+}
+
+#[test]
+fn tag_color_defaults_when_missing() {
+    let tag: TagInfo = serde_json::from_str(r#"{"id":"fc","name":"Full Combo","color":null}"#).unwrap();
+    assert_eq!(tag.color, Color::DEFAULT);
+    let tag: TagInfo = serde_json::from_str(r#"{"id":"fc","name":"Full Combo"}"#).unwrap();
+    assert_eq!(tag.color, Color::DEFAULT);
+    let tag: TagInfo = serde_json::from_str(r#"{"id":"fc","name":"Full Combo","color":{"r":1,"g":2,"b":3}}"#).unwrap();
+    assert_eq!(tag.color, Color::new(1, 2, 3));
+}
+
+fn tag(id: &str, name: &str) -> TagInfo {
+    TagInfo {
+        id: id.to_string(),
+        name: name.to_string(),
+        color: Color::DEFAULT,
+    }
+}
+
+#[test]
+fn tag_ids_are_unique() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut aux_data = LibraryAuxData::read_or_create_new_safe(dir.path().join("library_aux.json")).unwrap();
+    aux_data.add_tag(tag("fc", "Full Combo")).unwrap();
+    assert!(aux_data.add_tag(tag("fc", "Another Full Combo")).is_err());
+    assert_eq!(aux_data.tags().len(), 1);
+    assert_eq!(aux_data.get_tag("fc").unwrap().name, "Full Combo");
+}
+
+#[test]
+fn remove_rename_and_recolor_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    let aux_data_path = dir.path().join("library_aux.json");
+    {
+        let mut aux_data = LibraryAuxData::read_or_create_new_safe(&aux_data_path).unwrap();
+        aux_data.add_tag(tag("fc", "Full Combo")).unwrap();
+        aux_data.add_tag(tag("pb", "Personal Best")).unwrap();
+
+        aux_data.rename_tag("fc", "FC".to_string()).unwrap();
+        aux_data.set_tag_color("fc", Color::new(255, 215, 0)).unwrap();
+        assert!(aux_data.rename_tag("missing", "Missing".to_string()).is_err());
+
+        assert_eq!(aux_data.remove_tag("pb").unwrap().name, "Personal Best");
+        assert!(aux_data.remove_tag("pb").is_none());
+        aux_data.write_to_file().unwrap();
+    }
+
+    let aux_data = LibraryAuxData::read_or_create_new_safe(&aux_data_path).unwrap();
+    assert_eq!(aux_data.tags().len(), 1);
+    let fc = aux_data.get_tag("fc").unwrap();
+    assert_eq!(fc.name, "FC");
+    assert_eq!(fc.color.to_hex(), "#ffd700");
+}
+
+#[test]
+fn duplicated_tag_ids_are_reported_and_deduplicated() {
+    let dir = tempfile::tempdir().unwrap();
+    let aux_data_path = dir.path().join("library_aux.json");
+    fs::write(
+        &aux_data_path,
+        r##"{"tags":[
+            {"id":"fc","name":"Full Combo","color":{"r":0,"g":0,"b":0}},
+            {"id":"pb","name":"Personal Best","color":{"r":0,"g":0,"b":0}},
+            {"id":"fc","name":"FC","color":{"r":0,"g":0,"b":0}},
+            {"id":"fc","name":"Full Combo again","color":{"r":0,"g":0,"b":0}}
+        ]}"##,
+    )
+    .unwrap();
+
+    // loading only warns about duplicates
+    let mut aux_data = LibraryAuxData::read_or_create_new_safe(&aux_data_path).unwrap();
+    assert_eq!(aux_data.validate(), Err(vec!["fc".to_string()]));
+
+    assert_eq!(aux_data.dedup_tags(), 2);
+    assert_eq!(aux_data.validate(), Ok(()));
+    assert_eq!(aux_data.tags().len(), 2);
+    assert_eq!(aux_data.get_tag("fc").unwrap().name, "Full Combo");
+}
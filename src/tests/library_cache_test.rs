@@ -0,0 +1,203 @@
+use crate::library::cache::{HashingMethod, LibraryCache, compute_hash_of_bytes, compute_hash_of_file, file_metadata};
+use crate::util::timestamp::NsTimestamp;
+use std::fs;
+
+const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+#[test]
+fn hash_is_computed_then_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("video.mkv");
+    fs::write(&file_path, "hello").unwrap();
+    let cache_path = dir.path().join(LibraryCache::STANDARD_FILENAME);
+
+    let mut cache = LibraryCache::read_or_create_new(cache_path.clone()).unwrap();
+    assert_eq!(cache.find_or_compute_file_sha256_hash(&file_path).unwrap(), HELLO_SHA256);
+    assert!(cache_path.exists());
+
+    // Reload the cache from disk, so that the hash can only come from the cache file.
+    let mut cache = LibraryCache::read_or_create_new(cache_path).unwrap();
+    let metadata = fs::metadata(&file_path).unwrap();
+    let cached = cache.find_cached_sha256_hash(
+        "video.mkv",
+        metadata.len(),
+        metadata.created().unwrap().try_into().unwrap(),
+        metadata.modified().unwrap().try_into().unwrap(),
+    );
+    assert_eq!(cached.as_deref(), Some(HELLO_SHA256));
+    assert_eq!(cache.find_or_compute_file_sha256_hash(&file_path).unwrap(), HELLO_SHA256);
+}
+
+#[test]
+fn missing_file_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert!(cache.find_or_compute_file_sha256_hash(&dir.path().join("missing.mkv")).is_err());
+}
+
+#[test]
+fn filename_index_lookup_matches_linear_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+
+    // several entries share each filename, so that the candidate lists have more than one element
+    for i in 0..5000u64 {
+        let filename = format!("video_{}.mkv", i % 1000);
+        cache.insert(filename, i, NsTimestamp::from_nanos(i.into()), NsTimestamp::from_nanos((i * 2).into()), format!("hash_{i}"));
+    }
+    // re-inserting an existing entry only updates its hash
+    cache.insert("video_7.mkv".to_string(), 7, NsTimestamp::from_nanos(7), NsTimestamp::from_nanos(14), "updated".to_string());
+    assert_eq!(cache.entries().len(), 5000);
+
+    let linear = |filename: &str, file_size: u64, birth: NsTimestamp, modify: NsTimestamp| {
+        cache
+            .entries()
+            .iter()
+            .find(|e| e.filename == filename && e.file_size == file_size && e.birth_timestamp == birth && e.modify_timestamp == modify)
+            .map(|e| e.sha256.clone())
+    };
+
+    for i in (0..5500u64).step_by(7) {
+        let filename = format!("video_{}.mkv", i % 1000);
+        let birth = NsTimestamp::from_nanos(i.into());
+        let modify = NsTimestamp::from_nanos((i * 2).into());
+        assert_eq!(cache.find_cached_sha256_hash(&filename, i, birth, modify), linear(&filename, i, birth, modify));
+    }
+    assert_eq!(
+        cache.find_cached_sha256_hash("video_7.mkv", 7, NsTimestamp::from_nanos(7), NsTimestamp::from_nanos(14)).as_deref(),
+        Some("updated")
+    );
+
+    // the index has to be rebuilt correctly after reloading from disk
+    cache.write_to_file().unwrap();
+    let reloaded = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert_eq!(
+        reloaded.find_cached_sha256_hash("video_42.mkv", 2042, NsTimestamp::from_nanos(2042), NsTimestamp::from_nanos(4084)).as_deref(),
+        Some("hash_2042")
+    );
+}
+
+#[test]
+fn parallel_hashing_matches_sequential() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_paths: Vec<_> = (0..8)
+        .map(|i| {
+            let file_path = dir.path().join(format!("video_{i}.mkv"));
+            fs::write(&file_path, format!("contents of file {i}").repeat(i + 1)).unwrap();
+            file_path
+        })
+        .collect();
+    let paths: Vec<_> = file_paths.iter().map(|path| path.as_path()).collect();
+
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert_eq!(cache.compute_missing_hashes_parallel(&paths).unwrap(), 8);
+    assert_eq!(cache.compute_missing_hashes_parallel(&paths).unwrap(), 0);
+    assert_eq!(cache.entries().len(), 8);
+
+    for path in paths {
+        assert_eq!(cache.find_or_compute_file_sha256_hash(path).unwrap(), compute_hash_of_file(path, HashingMethod::SHA256).unwrap());
+    }
+}
+
+#[test]
+fn hashing_methods_are_distinct_and_stable() {
+    let sha256 = compute_hash_of_bytes(b"hello", HashingMethod::SHA256);
+    let blake3 = compute_hash_of_bytes(b"hello", HashingMethod::BLAKE3);
+    assert_eq!(sha256, HELLO_SHA256);
+    assert_eq!(blake3, "ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f");
+    assert_ne!(sha256, blake3);
+    assert_eq!(blake3, compute_hash_of_bytes(b"hello", HashingMethod::BLAKE3));
+}
+
+#[test]
+fn cache_entries_are_separated_by_hashing_method() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("video.mkv");
+    fs::write(&file_path, "hello").unwrap();
+
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert_eq!(cache.find_or_compute_file_sha256_hash(&file_path).unwrap(), HELLO_SHA256);
+
+    cache.set_hashing_method(HashingMethod::BLAKE3);
+    let blake3 = cache.find_or_compute_file_sha256_hash(&file_path).unwrap();
+    assert_eq!(blake3, compute_hash_of_bytes(b"hello", HashingMethod::BLAKE3));
+    assert_eq!(cache.entries().len(), 2);
+    assert_eq!(cache.entries()[1].hashing_method, HashingMethod::BLAKE3);
+}
+
+#[test]
+fn legacy_cache_entries_default_to_sha256() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join(LibraryCache::STANDARD_FILENAME);
+    fs::write(
+        &cache_path,
+        r#"{"files":[{"filename":"a.mkv","file_size":5,"birth_timestamp":1,"modify_timestamp":2,"sha256":"abc"}]}"#,
+    )
+    .unwrap();
+    let cache = LibraryCache::read_or_create_new(cache_path).unwrap();
+    assert_eq!(cache.entries()[0].hashing_method, HashingMethod::SHA256);
+    assert_eq!(cache.find_cached_sha256_hash("a.mkv", 5, NsTimestamp::from_nanos(1), NsTimestamp::from_nanos(2)).as_deref(), Some("abc"));
+}
+
+#[test]
+fn streamed_hash_matches_one_shot_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("medium.mkv");
+    // a few megabytes that do not divide evenly into hashing chunks
+    let bytes: Vec<u8> = (0..3_000_017u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    fs::write(&file_path, &bytes).unwrap();
+
+    for method in [HashingMethod::SHA256, HashingMethod::MD5, HashingMethod::BLAKE3] {
+        assert_eq!(compute_hash_of_file(&file_path, method).unwrap(), compute_hash_of_bytes(&bytes, method));
+    }
+    assert_eq!(
+        compute_hash_of_file(&file_path, HashingMethod::MD5).unwrap(),
+        format!("{:x}", md5::compute(&bytes))
+    );
+    assert!(compute_hash_of_file(&dir.path().join("missing.mkv"), HashingMethod::SHA256).is_err());
+}
+
+#[test]
+fn prune_missing_drops_absent_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    let present_path = dir.path().join("nested").join("present.mkv");
+    fs::write(&present_path, "present").unwrap();
+    let absent_path = dir.path().join("absent.mkv");
+    fs::write(&absent_path, "absent").unwrap();
+
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    cache.find_or_compute_file_sha256_hash(&present_path).unwrap();
+    cache.find_or_compute_file_sha256_hash(&absent_path).unwrap();
+    fs::remove_file(&absent_path).unwrap();
+
+    assert_eq!(cache.prune_missing(dir.path()), 1);
+    assert_eq!(cache.entries().len(), 1);
+    assert_eq!(cache.entries()[0].filename, "present.mkv");
+    assert_eq!(cache.prune_missing(dir.path()), 0);
+
+    // the filename index has to stay consistent with the remaining entries
+    let metadata = fs::metadata(&present_path).unwrap();
+    let cached = cache.find_cached_sha256_hash(
+        "present.mkv",
+        metadata.len(),
+        metadata.created().unwrap().try_into().unwrap(),
+        metadata.modified().unwrap().try_into().unwrap(),
+    );
+    assert_eq!(cached, Some(compute_hash_of_bytes(b"present", HashingMethod::SHA256)));
+}
+
+#[test]
+fn file_metadata_is_sensible() {
+    let before = NsTimestamp::now().truncate_to_secs();
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("video.mkv");
+    fs::write(&file_path, "hello").unwrap();
+
+    let (file_size, birth_timestamp, modify_timestamp) = file_metadata(&file_path).unwrap();
+    assert_eq!(file_size, 5);
+    assert!(birth_timestamp <= modify_timestamp);
+    assert!(modify_timestamp >= before);
+    assert!(modify_timestamp <= NsTimestamp::now());
+    assert!(file_metadata(&dir.path().join("missing.mkv")).is_err());
+}
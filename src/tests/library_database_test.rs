@@ -0,0 +1,396 @@
+use crate::library::cache::{HashingMethod, LibraryCache, compute_hash_of_bytes};
+use crate::library::database::{ChangeOperation, ChangeRecord, ContentDescription, LibraryDatabase, LibraryEntry, LibraryEntryKind, UnsupportedHashingMethod};
+use crate::util::file_ex::FileEx;
+use crate::util::path::PathError;
+use std::fs;
+
+#[test]
+fn add_is_recorded_in_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    database.enable_journal();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    let uuid = database.add(dir.path(), &video_path, "abc".to_string()).unwrap();
+
+    let journal_path = dir.path().join(LibraryDatabase::JOURNAL_FILENAME);
+    let records: Vec<ChangeRecord> = journal_path.read_from_jsonlines().unwrap().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, ChangeOperation::Add);
+    assert_eq!(records[0].uuid.0, uuid);
+}
+
+#[test]
+fn journal_is_disabled_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    database.add(dir.path(), &video_path, "abc".to_string()).unwrap();
+    assert!(!dir.path().join(LibraryDatabase::JOURNAL_FILENAME).exists());
+}
+
+#[test]
+fn failed_bulk_operation_is_rolled_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let first_path = dir.path().join("first.mkv");
+    let second_path = dir.path().join("second.mkv");
+    fs::write(&first_path, "first").unwrap();
+    fs::write(&second_path, "second").unwrap();
+    database.add(dir.path(), &first_path, "first".to_string()).unwrap();
+
+    let result: Result<(), &str> = database.with_rollback_on_err(|database| {
+        database.add(dir.path(), &second_path, "second".to_string()).unwrap();
+        assert_eq!(database.entries().len(), 2);
+        Err("something went wrong")
+    });
+
+    assert_eq!(result, Err("something went wrong"));
+    assert_eq!(database.entries().len(), 1);
+    assert_eq!(database.entries()[0].sha256, "first");
+}
+
+#[test]
+fn rolled_back_changes_are_not_recorded_in_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    database.enable_journal();
+    let first_path = dir.path().join("first.mkv");
+    let second_path = dir.path().join("second.mkv");
+    fs::write(&first_path, "first").unwrap();
+    fs::write(&second_path, "second").unwrap();
+    let first_uuid = database.add(dir.path(), &first_path, "first".to_string()).unwrap();
+
+    let result: Result<(), &str> = database.with_rollback_on_err(|database| {
+        database.add(dir.path(), &second_path, "second".to_string()).unwrap();
+        database.remove_entry(first_uuid).unwrap();
+        Err("something went wrong")
+    });
+    assert!(result.is_err());
+
+    let journal_path = dir.path().join(LibraryDatabase::JOURNAL_FILENAME);
+    let records: Vec<ChangeRecord> = journal_path.read_from_jsonlines().unwrap().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, ChangeOperation::Add);
+    assert_eq!(records[0].uuid.0, first_uuid);
+}
+
+#[test]
+fn successful_bulk_operation_is_recorded_in_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    database.enable_journal();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+
+    let uuid = database
+        .with_rollback_on_err(|database| {
+            let uuid = database.add(dir.path(), &video_path, "video".to_string())?;
+            let nested: Result<(), &str> = database.with_rollback_on_err(|database| {
+                database.remove_entry(uuid).unwrap();
+                Err("nested failure")
+            });
+            assert!(nested.is_err());
+            Ok::<_, PathError>(uuid)
+        })
+        .unwrap();
+
+    let journal_path = dir.path().join(LibraryDatabase::JOURNAL_FILENAME);
+    let records: Vec<ChangeRecord> = journal_path.read_from_jsonlines().unwrap().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, ChangeOperation::Add);
+    assert_eq!(records[0].uuid.0, uuid);
+    assert!(database.get_entry(uuid).is_some());
+}
+
+#[test]
+fn uploaded_entries_are_not_upload_candidates() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut uuids = Vec::new();
+    for name in ["first.mkv", "second.mkv"] {
+        let path = dir.path().join(name);
+        fs::write(&path, name).unwrap();
+        uuids.push(database.add(dir.path(), &path, name.to_string()).unwrap());
+    }
+
+    database.mark_uploaded(uuids[0], "dQw4w9WgXcQ".to_string()).unwrap();
+
+    let uploaded = database.get_entry(uuids[0]).unwrap();
+    assert_eq!(uploaded.youtube_video_id.as_deref(), Some("dQw4w9WgXcQ"));
+    assert!(uploaded.upload_timestamp.is_some());
+    let candidates = database.not_yet_uploaded(LibraryEntryKind::Unspecified);
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].uuid.0, uuids[1]);
+    assert!(database.not_yet_uploaded(LibraryEntryKind::Linked).is_empty());
+}
+
+#[test]
+fn appended_entries_are_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let database_path = dir.path().join(LibraryDatabase::STANDARD_FILENAME);
+    let mut uuids = Vec::new();
+    {
+        let mut database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+        for name in ["first.mkv", "second.mkv", "third.mkv"] {
+            let path = dir.path().join(name);
+            fs::write(&path, name).unwrap();
+            uuids.push(database.add_and_append(dir.path(), &path, name.to_string()).unwrap());
+        }
+    }
+
+    let database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+    let loaded: Vec<_> = database.entries().iter().map(|entry| entry.uuid.0).collect();
+    assert_eq!(loaded, uuids);
+}
+
+#[test]
+fn adding_same_hash_twice_merges_urls() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    fs::write(dir.path().join("first.mkv"), "video").unwrap();
+    fs::write(dir.path().join("second.mkv"), "video").unwrap();
+
+    let first_uuid = database.add(dir.path(), &dir.path().join("first.mkv"), "abc".to_string()).unwrap();
+    let second_uuid = database.add(dir.path(), &dir.path().join("second.mkv"), "abc".to_string()).unwrap();
+
+    assert_eq!(first_uuid, second_uuid);
+    assert_eq!(database.entries().len(), 1);
+    let urls = &database.entries()[0].library_urls;
+    assert_eq!(urls.len(), 2);
+    assert!(urls[0].ends_with("/first.mkv"));
+    assert!(urls[1].ends_with("/second.mkv"));
+}
+
+#[test]
+fn add_and_append_does_not_duplicate_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let database_path = dir.path().join(LibraryDatabase::STANDARD_FILENAME);
+    fs::write(dir.path().join("first.mkv"), "video").unwrap();
+    fs::write(dir.path().join("second.mkv"), "video").unwrap();
+    {
+        let mut database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+        database.add_and_append(dir.path(), &dir.path().join("first.mkv"), "abc".to_string()).unwrap();
+        database.add_and_append(dir.path(), &dir.path().join("second.mkv"), "abc".to_string()).unwrap();
+    }
+
+    let database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+    assert_eq!(database.entries().len(), 1);
+    assert_eq!(database.entries()[0].library_urls.len(), 2);
+}
+
+#[test]
+fn sha256_lookup_matches_brute_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let database_path = dir.path().join(LibraryDatabase::STANDARD_FILENAME);
+    let mut database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+    for i in 0..300 {
+        let video_path = dir.path().join(format!("video_{i}.mkv"));
+        fs::write(&video_path, "video").unwrap();
+        // every third file is a duplicate of an earlier one
+        database.add(dir.path(), &video_path, format!("hash_{}", i - i % 3)).unwrap();
+    }
+    assert_eq!(database.entries().len(), 100);
+
+    let brute_force = |database: &LibraryDatabase, sha256: &str| database.entries().iter().find(|entry| entry.sha256 == sha256).map(|entry| entry.uuid);
+    for i in 0..320 {
+        let sha256 = format!("hash_{i}");
+        assert_eq!(database.find_entry_by_sha256_hash(&sha256).map(|entry| entry.uuid), brute_force(&database, &sha256));
+    }
+
+    // the index is rebuilt when loading the database again
+    database.write_to_file().unwrap();
+    drop(database);
+    let database = LibraryDatabase::read_or_create_new_safe(&database_path).unwrap();
+    for i in (0..320).step_by(7) {
+        let sha256 = format!("hash_{i}");
+        assert_eq!(database.find_entry_by_sha256_hash(&sha256).map(|entry| entry.uuid), brute_force(&database, &sha256));
+    }
+}
+
+#[test]
+fn library_urls_use_domain_and_relative_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let library_dir = dir.path().join("library");
+    fs::create_dir_all(library_dir.join("yarg").join("2024")).unwrap();
+    let video_path = library_dir.join("yarg").join("2024").join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    let outside_path = dir.path().join("outside.mkv");
+    fs::write(&outside_path, "outside").unwrap();
+
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    database.set_domain_name("proofs.example.org".to_string());
+    let uuid = database.add(&library_dir, &video_path, "abc".to_string()).unwrap();
+
+    assert_eq!(database.get_entry(uuid).unwrap().library_urls, vec!["stpl://proofs.example.org/yarg/2024/video.mkv".to_string()]);
+    assert!(matches!(
+        database.add(&library_dir, &outside_path, "def".to_string()),
+        Err(PathError::OutsideOfRoot)
+    ));
+    assert_eq!(database.entries().len(), 1);
+}
+
+#[test]
+fn update_entry_replaces_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    database.enable_journal();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    let uuid = database.add(dir.path(), &video_path, "abc".to_string()).unwrap();
+
+    let mut entry = database.get_entry(uuid).unwrap().clone();
+    entry.tags.insert("full-combo".to_string());
+    database.update_entry(entry).unwrap();
+    assert!(database.get_entry(uuid).unwrap().tags.contains("full-combo"));
+
+    database.get_entry_mut(uuid).unwrap().tags.insert("personal-best".to_string());
+    assert_eq!(database.get_entry(uuid).unwrap().tags.len(), 2);
+
+    let unknown = LibraryEntry::default();
+    assert!(database.update_entry(unknown).is_err());
+
+    let records: Vec<ChangeRecord> = dir.path().join(LibraryDatabase::JOURNAL_FILENAME).read_from_jsonlines().unwrap().unwrap();
+    assert_eq!(records.last().unwrap().operation, ChangeOperation::Update);
+}
+
+#[test]
+fn remove_entry_keeps_sha256_lookup_in_sync() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut uuids = Vec::new();
+    for name in ["first", "second", "third"] {
+        let video_path = dir.path().join(format!("{name}.mkv"));
+        fs::write(&video_path, name).unwrap();
+        uuids.push(database.add(dir.path(), &video_path, name.to_string()).unwrap());
+    }
+
+    let removed = database.remove_entry(uuids[0]).unwrap();
+    assert_eq!(removed.sha256, "first");
+    assert!(database.remove_entry(uuids[0]).is_none());
+    assert_eq!(database.entries().len(), 2);
+    assert!(database.find_entry_by_sha256_hash("first").is_none());
+    assert_eq!(database.find_entry_by_sha256_hash("third").unwrap().uuid.0, uuids[2]);
+}
+
+#[test]
+fn query_by_tag_kind_and_game() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut uuids = Vec::new();
+    for name in ["first", "second", "third"] {
+        let video_path = dir.path().join(format!("{name}.mkv"));
+        fs::write(&video_path, name).unwrap();
+        uuids.push(database.add(dir.path(), &video_path, name.to_string()).unwrap());
+    }
+
+    let first = database.get_entry_mut(uuids[0]).unwrap();
+    first.tags.insert("fc".to_string());
+    first.entry_kind = LibraryEntryKind::Linked;
+    first.content_description = ContentDescription::GameplayNormal {
+        game: Some("yarg".to_string()),
+    };
+    let second = database.get_entry_mut(uuids[1]).unwrap();
+    second.tags.insert("fc".to_string());
+    second.content_description = ContentDescription::ResultsScreen {
+        game: Some("osu".to_string()),
+    };
+    database.get_entry_mut(uuids[2]).unwrap().content_description = ContentDescription::GameGeneric { game: None };
+
+    let uuids_of = |entries: Vec<&LibraryEntry>| entries.iter().map(|entry| entry.uuid.0).collect::<Vec<_>>();
+    assert_eq!(uuids_of(database.entries_with_tag("fc")), vec![uuids[0], uuids[1]]);
+    assert!(database.entries_with_tag("pb").is_empty());
+    assert_eq!(uuids_of(database.entries_of_kind(LibraryEntryKind::Linked)), vec![uuids[0]]);
+    assert_eq!(uuids_of(database.entries_of_kind(LibraryEntryKind::Unspecified)), vec![uuids[1], uuids[2]]);
+    assert_eq!(uuids_of(database.entries_by_game(&"osu".to_string())), vec![uuids[1]]);
+    assert!(database.entries_by_game(&"sdvx".to_string()).is_empty());
+    assert_eq!(ContentDescription::Other.game_id(), None);
+}
+
+#[test]
+fn verify_presence_reports_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    let present_path = dir.path().join("nested").join("present.mkv");
+    let missing_path = dir.path().join("missing.mkv");
+    fs::write(&present_path, "present").unwrap();
+    fs::write(&missing_path, "missing").unwrap();
+    let present_uuid = database.add(dir.path(), &present_path, "present".to_string()).unwrap();
+    let missing_uuid = database.add(dir.path(), &missing_path, "missing".to_string()).unwrap();
+    fs::remove_file(&missing_path).unwrap();
+
+    let missing = database.verify_presence(dir.path());
+
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].0, missing_uuid);
+    assert!(database.get_entry(present_uuid).unwrap().present);
+    assert!(!database.get_entry(missing_uuid).unwrap().present);
+}
+
+#[test]
+fn present_defaults_to_true_for_old_entries() {
+    let mut value = serde_json::to_value(LibraryEntry::default()).unwrap();
+    value.as_object_mut().unwrap().remove("present");
+    let entry: LibraryEntry = serde_json::from_value(value).unwrap();
+    assert!(entry.present);
+}
+
+#[test]
+fn verify_hashes_reports_altered_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    let intact_path = dir.path().join("intact.mkv");
+    let altered_path = dir.path().join("altered.mkv");
+    fs::write(&intact_path, "intact").unwrap();
+    fs::write(&altered_path, "altered").unwrap();
+    for path in [&intact_path, &altered_path] {
+        let sha256 = cache.find_or_compute_file_sha256_hash(path).unwrap();
+        database.add(dir.path(), path, sha256).unwrap();
+    }
+    let altered_uuid = database.entries()[1].uuid;
+    fs::write(&altered_path, "bit rot").unwrap();
+
+    let mismatches = database.verify_hashes(dir.path(), &mut cache).unwrap();
+
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].uuid, altered_uuid);
+    assert!(mismatches[0].url.ends_with("/altered.mkv"));
+    assert_eq!(mismatches[0].expected, compute_hash_of_bytes(b"altered", HashingMethod::SHA256));
+    assert_eq!(mismatches[0].actual, compute_hash_of_bytes(b"bit rot", HashingMethod::SHA256));
+}
+
+#[test]
+fn verify_hashes_saves_recomputed_hashes() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join(LibraryCache::STANDARD_FILENAME);
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut cache = LibraryCache::read_or_create_new(cache_path.clone()).unwrap();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    database.add(dir.path(), &video_path, compute_hash_of_bytes(b"video", HashingMethod::SHA256)).unwrap();
+    assert!(!cache_path.exists());
+
+    database.verify_hashes(dir.path(), &mut cache).unwrap();
+
+    let saved_cache = LibraryCache::read_or_create_new(cache_path.clone()).unwrap();
+    assert!(saved_cache.is_file_cached(&video_path));
+}
+
+#[test]
+fn verify_hashes_refuses_other_hashing_methods() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    let video_path = dir.path().join("video.mkv");
+    fs::write(&video_path, "video").unwrap();
+    database.add(dir.path(), &video_path, compute_hash_of_bytes(b"video", HashingMethod::SHA256)).unwrap();
+    cache.set_hashing_method(HashingMethod::BLAKE3);
+
+    let result = database.verify_hashes(dir.path(), &mut cache);
+
+    assert!(matches!(result, Err(UnsupportedHashingMethod(HashingMethod::BLAKE3))));
+    assert!(!cache.is_file_cached(&video_path));
+}
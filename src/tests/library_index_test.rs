@@ -0,0 +1,223 @@
+use crate::library::cache::{HashingMethod, LibraryCache, compute_hash_of_file};
+use crate::library::database::LibraryDatabase;
+use crate::library::index::{LibraryIndex, ScanError, ScanOptions, ScanProgress};
+use crate::util::uuid::UuidString;
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn merge_reports_collisions() {
+    let kept_uuid = UuidString::new_v4();
+    let mut first = LibraryIndex::default();
+    first.files.insert(PathBuf::from("a.mkv"), UuidString::new_v4());
+    first.files.insert(PathBuf::from("shared.mkv"), kept_uuid);
+    let mut second = LibraryIndex::default();
+    second.files.insert(PathBuf::from("b.mkv"), UuidString::new_v4());
+    second.files.insert(PathBuf::from("shared.mkv"), UuidString::new_v4());
+
+    let collisions = first.merge(second);
+
+    assert_eq!(collisions, vec![PathBuf::from("shared.mkv")]);
+    assert_eq!(first.files.len(), 3);
+    assert_eq!(first.files[&PathBuf::from("shared.mkv")], kept_uuid);
+}
+
+#[test]
+fn scannable_extensions_are_case_insensitive() {
+    let options = ScanOptions::default();
+    assert!(options.should_file_be_scanned("video.mkv"));
+    assert!(options.should_file_be_scanned("screenshot.PNG"));
+    assert!(options.should_file_be_scanned("recording.WebM"));
+    assert!(!options.should_file_be_scanned("notes.txt"));
+    assert!(!options.should_file_be_scanned("mkv"));
+    assert!(LibraryIndex::should_file_be_scanned("clip.mp4"));
+}
+
+#[test]
+fn scan_uses_configured_extensions() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("replay.yargrep"), "replay").unwrap();
+    fs::write(dir.path().join("video.MKV"), "video").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+
+    let options = ScanOptions {
+        scannable_extensions: vec!["yargrep".to_string()],
+        ..Default::default()
+    };
+    let index = LibraryIndex::scan_library_dir_with_options(dir.path(), &mut database, &options).unwrap();
+
+    assert_eq!(index.files.len(), 1);
+    assert!(index.files.contains_key(&PathBuf::from("replay.yargrep")));
+}
+
+#[test]
+fn incremental_scan_only_hashes_new_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("first.mkv"), "first").unwrap();
+    fs::write(dir.path().join("second.mkv"), "second").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let mut previous = LibraryIndex::scan_library_dir(dir.path(), &mut database).unwrap();
+    assert_eq!(previous.files.len(), 2);
+
+    // an entry taken from the previous index is kept as-is, without looking it up again
+    let reused_uuid = UuidString::new_v4();
+    previous.files.insert(PathBuf::from("first.mkv"), reused_uuid);
+    fs::remove_file(dir.path().join("second.mkv")).unwrap();
+    fs::write(dir.path().join("third.mkv"), "third").unwrap();
+
+    let index = LibraryIndex::scan_library_dir_incremental(dir.path(), &mut database, &previous).unwrap();
+
+    assert_eq!(index.files.len(), 2);
+    assert_eq!(index.files[&PathBuf::from("first.mkv")], reused_uuid);
+    assert!(index.files.contains_key(&PathBuf::from("third.mkv")));
+    assert!(!index.files.contains_key(&PathBuf::from("second.mkv")));
+
+    // only the new file got hashed in addition to the two from the first scan
+    let cache = LibraryCache::read_or_create_new(dir.path().join(LibraryCache::STANDARD_FILENAME)).unwrap();
+    assert_eq!(cache.entries().len(), 3);
+    assert_eq!(database.entries().len(), 3);
+}
+
+#[test]
+fn incremental_scan_rehashes_changed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("first.mkv"), "first").unwrap();
+    fs::write(dir.path().join("second.mkv"), "second").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let previous = LibraryIndex::scan_library_dir(dir.path(), &mut database).unwrap();
+    let old_uuid = previous.files[&PathBuf::from("first.mkv")];
+
+    // a different size guarantees different metadata, even on filesystems with coarse timestamps
+    fs::write(dir.path().join("first.mkv"), "rewritten first").unwrap();
+
+    let index = LibraryIndex::scan_library_dir_incremental(dir.path(), &mut database, &previous).unwrap();
+
+    let new_uuid = index.files[&PathBuf::from("first.mkv")];
+    assert_ne!(new_uuid, old_uuid);
+    assert_eq!(index.files[&PathBuf::from("second.mkv")], previous.files[&PathBuf::from("second.mkv")]);
+    let new_hash = compute_hash_of_file(&dir.path().join("first.mkv"), HashingMethod::SHA256).unwrap();
+    assert_eq!(database.find_entry_by_sha256_hash(&new_hash).unwrap().uuid, new_uuid);
+    assert_eq!(database.entries().len(), 3);
+}
+
+#[test]
+fn duplicate_files_are_recorded_in_one_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("copies")).unwrap();
+    fs::write(dir.path().join("original.mkv"), "same contents").unwrap();
+    fs::write(dir.path().join("copies").join("copy.mkv"), "same contents").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+
+    let index = LibraryIndex::scan_library_dir(dir.path(), &mut database).unwrap();
+
+    assert_eq!(index.files.len(), 2);
+    assert_eq!(database.entries().len(), 1);
+    let mut urls = database.entries()[0].library_urls.clone();
+    urls.sort();
+    assert_eq!(urls.len(), 2);
+    assert!(urls[0].ends_with("/copies/copy.mkv"));
+    assert!(urls[1].ends_with("/original.mkv"));
+
+    // rescanning does not add the same urls again
+    LibraryIndex::scan_library_dir(dir.path(), &mut database).unwrap();
+    assert_eq!(database.entries()[0].library_urls.len(), 2);
+}
+
+#[test]
+fn scan_reports_progress_for_every_file() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("first.mkv"), "first").unwrap();
+    fs::write(dir.path().join("second.mp4"), "second").unwrap();
+    fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    // the database file and its lockfile are walked too
+    let walked = fs::read_dir(dir.path()).unwrap().count();
+
+    let mut events: Vec<ScanProgress> = Vec::new();
+    let index = LibraryIndex::scan_library_dir_with_progress(dir.path(), &mut database, |progress| events.push(progress)).unwrap();
+
+    assert_eq!(index.files.len(), 2);
+    assert_eq!(events.len(), walked);
+    assert!(events.iter().enumerate().all(|(i, progress)| progress.index == i && progress.total == walked));
+    // only the text file and the lockfile can be skipped, in whatever order they were walked
+    assert!(events.windows(2).all(|pair| pair[0].skipped <= pair[1].skipped));
+    assert!(events.last().unwrap().skipped <= walked - 2);
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let index_path = dir.path().join(LibraryIndex::STANDARD_FILENAME);
+    let mut index = LibraryIndex::default();
+    index.files.insert(PathBuf::from("a.mkv"), UuidString::new_v4());
+    index.files.insert(PathBuf::from("nested/b.mp4"), UuidString::new_v4());
+
+    index.save(&index_path).unwrap();
+    assert_eq!(LibraryIndex::load(&index_path).unwrap(), index);
+    assert!(LibraryIndex::load(&dir.path().join("missing.json")).is_err());
+}
+
+#[test]
+fn unreadable_file_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["first.mkv", "second.mkv", "third.mkv"] {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+
+    // simulate a file being deleted while the scan is running
+    let mut deleted: Option<PathBuf> = None;
+    let mut events: Vec<ScanProgress> = Vec::new();
+    let index = LibraryIndex::scan_library_dir_with_progress(dir.path(), &mut database, |progress| {
+        if deleted.is_none() && progress.current_path.extension().is_some_and(|extension| extension == "mkv") {
+            fs::remove_file(&progress.current_path).unwrap();
+            deleted = Some(progress.current_path.clone());
+        }
+        events.push(progress);
+    })
+    .unwrap();
+
+    let deleted_name = deleted.unwrap().file_name().unwrap().to_owned();
+    assert_eq!(index.files.len(), 2);
+    assert!(!index.files.contains_key(&PathBuf::from(&deleted_name)));
+
+    // the first video is never the last walked file, so the skip shows up in the last progress event
+    let (last, before_last) = events.split_last().unwrap();
+    let other_skipped = before_last
+        .iter()
+        .filter(|progress| progress.current_path.extension().is_none_or(|extension| extension != "mkv"))
+        .count();
+    assert_eq!(last.skipped, other_skipped + 1);
+}
+
+#[test]
+fn missing_library_dir_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+    let result = LibraryIndex::scan_library_dir(&dir.path().join("missing"), &mut database);
+    assert!(matches!(result, Err(ScanError::CannotWalkDir(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loop_scan_terminates() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("nested")).unwrap();
+    fs::write(dir.path().join("nested").join("video.mkv"), "video").unwrap();
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("nested").join("loop")).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("nested"), dir.path().join("shortcut")).unwrap();
+    let mut database = LibraryDatabase::read_or_create_new_safe(dir.path().join(LibraryDatabase::STANDARD_FILENAME)).unwrap();
+
+    let index = LibraryIndex::scan_library_dir(dir.path(), &mut database).unwrap();
+    assert_eq!(index.files.len(), 1);
+
+    let options = ScanOptions {
+        follow_symlinks: true,
+        ..Default::default()
+    };
+    let index = LibraryIndex::scan_library_dir_with_options(dir.path(), &mut database, &options).unwrap();
+    assert_eq!(index.files.len(), 1);
+    assert!(index.files.contains_key(&PathBuf::from("nested/video.mkv")));
+    assert_eq!(database.entries().len(), 1);
+    assert_eq!(database.entries()[0].library_urls.len(), 1);
+}
@@ -0,0 +1,118 @@
+use crate::util::lockfile::{Error, LockfileHandle, LockfileInfo, lockfile_verbose, set_lockfile_verbose};
+use crate::util::timestamp::NsTimestamp;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+#[cfg(target_os = "linux")]
+fn stale_lockfile_from_dead_process_is_reclaimed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    let lockfile_path = LockfileHandle::lockfile_path_for(&path).unwrap();
+    let stale_info = LockfileInfo {
+        version: "0.1.0".to_string(),
+        pid: 999_999_999,
+        lock_timestamp: NsTimestamp::from_nanos(0),
+    };
+    fs::write(&lockfile_path, stale_info.to_lockfile_contents()).unwrap();
+
+    assert!(LockfileHandle::acquire(&path).unwrap_err().is_already_locked());
+
+    let handle = LockfileHandle::acquire_wait_reclaim_stale(&path).unwrap();
+    let info = LockfileHandle::read_holder_info(handle.main_file_path()).unwrap().unwrap();
+    assert_eq!(info.pid, std::process::id());
+}
+
+#[test]
+fn acquire_wait_timeout_gives_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    let _handle = LockfileHandle::acquire(&path).unwrap();
+
+    let start = Instant::now();
+    let result = LockfileHandle::acquire_wait_timeout(&path, Duration::from_millis(100));
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn acquire_wait_timeout_gives_up_while_lockfile_keeps_changing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    let _handle = LockfileHandle::acquire(&path).unwrap();
+    let lockfile_path = LockfileHandle::lockfile_path_for(&path).unwrap();
+    let contents = fs::read(&lockfile_path).unwrap();
+
+    let stop = AtomicBool::new(false);
+    let start = Instant::now();
+    let result = thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    fs::write(&lockfile_path, &contents).unwrap();
+                }
+            });
+        }
+        let result = LockfileHandle::acquire_wait_timeout(&path, Duration::from_millis(100));
+        stop.store(true, Ordering::Relaxed);
+        result
+    });
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn acquire_wait_timeout_succeeds_when_unlocked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    assert!(LockfileHandle::acquire_wait_timeout(&path, Duration::from_millis(100)).is_ok());
+}
+
+#[test]
+fn lockfile_info_round_trip() {
+    let info = LockfileInfo {
+        version: "1.2.3".to_string(),
+        pid: 1234,
+        lock_timestamp: NsTimestamp::from_nanos(1_758_412_800_123_456_789),
+    };
+    assert_eq!(LockfileInfo::parse(&info.to_lockfile_contents()), Some(info));
+    assert_eq!(LockfileInfo::parse("pid: not a number"), None);
+}
+
+#[test]
+fn holder_info_of_held_and_free_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    assert_eq!(LockfileHandle::read_holder_info(&path).unwrap(), None);
+
+    let _handle = LockfileHandle::acquire(&path).unwrap();
+    let info = LockfileHandle::read_holder_info(&path).unwrap().unwrap();
+    assert_eq!(info.pid, std::process::id());
+    assert_eq!(info.version, crate::VERSION);
+}
+
+#[test]
+fn verbosity_can_be_toggled() {
+    let initial = lockfile_verbose();
+    set_lockfile_verbose(true);
+    assert!(lockfile_verbose());
+    set_lockfile_verbose(false);
+    assert!(!lockfile_verbose());
+    set_lockfile_verbose(initial);
+}
+
+#[test]
+fn force_unlock_removes_leftover_lockfile() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("database.json");
+    let lockfile_path = LockfileHandle::lockfile_path_for(&path).unwrap();
+    fs::write(&lockfile_path, "leftover").unwrap();
+
+    LockfileHandle::force_unlock(&path).unwrap();
+    assert!(!lockfile_path.exists());
+    // Unlocking a file that is not locked is fine too.
+    LockfileHandle::force_unlock(&path).unwrap();
+    assert!(LockfileHandle::acquire(&path).is_ok());
+}
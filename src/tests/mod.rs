@@ -1,2 +1,28 @@
 #[cfg(test)]
 pub mod example_test;
+#[cfg(test)]
+pub mod job_test;
+#[cfg(test)]
+pub mod timestamp_test;
+#[cfg(test)]
+pub mod library_database_test;
+#[cfg(test)]
+pub mod path_test;
+#[cfg(test)]
+pub mod uuid_test;
+#[cfg(test)]
+pub mod lockfile_test;
+#[cfg(test)]
+pub mod queue_test;
+#[cfg(test)]
+pub mod library_index_test;
+#[cfg(test)]
+pub mod play_test;
+#[cfg(test)]
+pub mod file_ex_test;
+#[cfg(test)]
+pub mod library_cache_test;
+#[cfg(test)]
+pub mod library_aux_data_test;
+#[cfg(test)]
+pub mod task_test;
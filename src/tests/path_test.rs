@@ -0,0 +1,33 @@
+use crate::util::path::{PathError, to_library_relative};
+use std::fs;
+
+#[test]
+fn path_inside_root() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir(root.path().join("sub")).unwrap();
+    fs::write(root.path().join("sub").join("video.mkv"), "").unwrap();
+
+    let relative = to_library_relative(root.path(), &root.path().join("sub").join("video.mkv")).unwrap();
+    assert_eq!(relative, "sub/video.mkv");
+}
+
+#[test]
+fn path_with_parent_components() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir(root.path().join("sub")).unwrap();
+    fs::write(root.path().join("video.mkv"), "").unwrap();
+
+    let relative = to_library_relative(root.path(), &root.path().join("sub").join("..").join("video.mkv")).unwrap();
+    assert_eq!(relative, "video.mkv");
+}
+
+#[test]
+fn path_outside_root() {
+    let parent = tempfile::tempdir().unwrap();
+    fs::create_dir(parent.path().join("library")).unwrap();
+    fs::write(parent.path().join("outside.mkv"), "").unwrap();
+
+    let root = parent.path().join("library");
+    let result = to_library_relative(&root, &root.join("..").join("outside.mkv"));
+    assert!(matches!(result, Err(PathError::OutsideOfRoot)));
+}
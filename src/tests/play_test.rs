@@ -0,0 +1,159 @@
+use crate::game::yarg::{self, Difficulty, Instrument, Mode};
+use crate::play::{ChartKey, PlayDatabase, PlayTrait, best_per_chart};
+use crate::util::lockfile::LockfileHandle;
+use crate::util::timestamp::NsTimestamp;
+use crate::util::uuid::UuidString;
+use serde::{Deserialize, Serialize};
+
+fn yarg_play(song_id: &str, instrument: Instrument, difficulty: Difficulty, score: u64) -> yarg::Play {
+    yarg::Play {
+        song_id: song_id.to_string(),
+        instrument,
+        difficulty,
+        mode: Mode::Quickplay,
+        score,
+        notes_hit: 0,
+        max_streak: 0,
+        overhits: 0,
+        song_speed: 100.0,
+        modifiers: Vec::new(),
+        game_version: "v0.12.6".to_string(),
+        proof: Vec::new(),
+        timestamp: NsTimestamp::from_secs(0),
+        comment: String::new(),
+    }
+}
+
+#[test]
+fn yarg_accessors_through_trait_object() {
+    let play: Box<dyn PlayTrait> = Box::new(yarg_play("song", Instrument::Drums, Difficulty::ExpertPlus, 123_456));
+    assert_eq!(play.song_id(), "song");
+    assert_eq!(play.instrument(), "drums");
+    assert_eq!(play.difficulty(), "expert_plus");
+    assert_eq!(play.score(), 123_456);
+}
+
+#[test]
+fn play_database_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(PlayDatabase::STANDARD_FILENAME);
+    let mut database = PlayDatabase::read_or_create_new_safe(&path).unwrap();
+    assert!(database.plays().is_empty());
+    database.add_play(Box::new(yarg_play("song", Instrument::Guitar, Difficulty::Expert, 98_765)));
+    database.write_to_file().unwrap();
+    drop(database);
+
+    let database = PlayDatabase::read_or_create_new_safe(&path).unwrap();
+    assert_eq!(database.format_version(), PlayDatabase::FORMAT_VERSION);
+    assert_eq!(database.plays().len(), 1);
+    let play = &database.plays()[0];
+    assert_eq!(play.song_id(), "song");
+    assert_eq!(play.instrument(), "guitar");
+    assert_eq!(play.difficulty(), "expert");
+    assert_eq!(play.score(), 98_765);
+}
+
+#[test]
+fn best_per_chart_picks_top_score() {
+    let plays: Vec<Box<dyn PlayTrait>> = vec![
+        Box::new(yarg_play("song", Instrument::Guitar, Difficulty::Expert, 100_000)),
+        Box::new(yarg_play("song", Instrument::Guitar, Difficulty::Expert, 250_000)),
+        Box::new(yarg_play("song", Instrument::Guitar, Difficulty::Expert, 180_000)),
+        Box::new(yarg_play("song", Instrument::Guitar, Difficulty::Hard, 300_000)),
+        Box::new(yarg_play("song", Instrument::Drums, Difficulty::Expert, 50_000)),
+    ];
+
+    let best = best_per_chart(&plays);
+
+    assert_eq!(best.len(), 3);
+    let expert_guitar = ChartKey {
+        song_id: "song".to_string(),
+        instrument: "guitar".to_string(),
+        difficulty: "expert".to_string(),
+    };
+    assert_eq!(plays[1].chart_key(), expert_guitar);
+    assert_eq!(best[&expert_guitar].score(), 250_000);
+    assert_eq!(best[&plays[3].chart_key()].score(), 300_000);
+    assert_eq!(best[&plays[4].chart_key()].score(), 50_000);
+}
+
+/// Minimal play of a made-up game, to check that plays are persisted independently of the game they belong to.
+#[derive(Serialize, Deserialize)]
+struct MinimalPlay {
+    score: u64,
+}
+
+#[typetag::serde]
+impl PlayTrait for MinimalPlay {
+    fn proof(&self) -> Vec<UuidString> {
+        Vec::new()
+    }
+    fn timestamp(&self) -> NsTimestamp {
+        NsTimestamp::from_secs(0)
+    }
+    fn comment(&self) -> String {
+        String::new()
+    }
+    fn score(&self) -> u64 {
+        self.score
+    }
+    fn song_id(&self) -> String {
+        "minimal_song".to_string()
+    }
+    fn instrument(&self) -> String {
+        "kazoo".to_string()
+    }
+    fn difficulty(&self) -> String {
+        "normal".to_string()
+    }
+}
+
+#[test]
+fn play_database_round_trip_of_any_play_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(PlayDatabase::STANDARD_FILENAME);
+    let mut database = PlayDatabase::read_or_create_new_safe(&path).unwrap();
+    database.add_play(Box::new(MinimalPlay { score: 42 }));
+    database.add_play(Box::new(yarg_play("song", Instrument::Drums, Difficulty::Easy, 1_000)));
+    database.write_to_file().unwrap();
+
+    // the file stays locked until the database is dropped
+    assert!(LockfileHandle::acquire(&path).is_err_and(|e| e.is_already_locked()));
+    drop(database);
+
+    let database = PlayDatabase::read_or_create_new_safe(&path).unwrap();
+    assert_eq!(database.plays().len(), 2);
+    assert_eq!(database.plays()[0].song_id(), "minimal_song");
+    assert_eq!(database.plays()[0].score(), 42);
+    assert_eq!(database.plays()[1].song_id(), "song");
+}
+
+#[test]
+fn yarg_difficulties_sort_from_easiest() {
+    let mut difficulties = vec![
+        Difficulty::Hard,
+        Difficulty::ExpertPlus,
+        Difficulty::Beginner,
+        Difficulty::Expert,
+        Difficulty::Medium,
+        Difficulty::Easy,
+    ];
+    difficulties.sort();
+    assert_eq!(difficulties, Difficulty::ALL);
+}
+
+#[test]
+fn yarg_names_round_trip_through_from_str() {
+    for difficulty in Difficulty::ALL {
+        let name = difficulty.to_string();
+        assert_eq!(serde_json::to_value(&difficulty).unwrap(), name.as_str());
+        assert_eq!(name.parse::<Difficulty>().unwrap(), difficulty);
+    }
+    for instrument in Instrument::ALL {
+        let name = instrument.to_string();
+        assert_eq!(serde_json::to_value(&instrument).unwrap(), name.as_str());
+        assert_eq!(name.parse::<Instrument>().unwrap(), instrument);
+    }
+    assert!("ExpertPlus".parse::<Difficulty>().is_err());
+    assert!("bass".parse::<Instrument>().is_err());
+}
@@ -0,0 +1,203 @@
+use crate::hive::job::Job;
+use crate::hive::queue::TaskQueue;
+use crate::hive::task::{Task, TaskState};
+use crate::util::timestamp::NsTimestamp;
+use std::collections::HashMap;
+use std::path::Path;
+
+fn open_queue(dir: &Path, filename: &str) -> TaskQueue {
+    TaskQueue::read_or_create_new_safe(dir.join(filename)).unwrap()
+}
+
+fn task_in_state(name: &str, state: TaskState) -> Task {
+    let mut task = Task::new(name.to_string(), Job::Sleep { time_nanos: 0 });
+    task.state = state;
+    task
+}
+
+#[test]
+fn failed_tasks_move_to_dead_letter_and_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+    let mut dead_letter = open_queue(dir.path(), TaskQueue::DEAD_LETTER_FILENAME);
+
+    let failed = task_in_state("failed", TaskState::Failed);
+    let failed_uuid = failed.uuid.0;
+    queue.add_task(task_in_state("queued", TaskState::Queued)).unwrap();
+    queue.add_task(failed).unwrap();
+    queue.add_task(task_in_state("also failed", TaskState::Failed)).unwrap();
+    queue.add_task(task_in_state("done", TaskState::Done)).unwrap();
+
+    assert_eq!(queue.move_failed_to_dead_letter(&mut dead_letter), 2);
+    assert!(queue.get_task(failed_uuid).is_none());
+    assert!(dead_letter.get_task(failed_uuid).is_some());
+
+    queue.requeue_from_dead_letter(&mut dead_letter, failed_uuid).unwrap();
+    assert!(dead_letter.get_task(failed_uuid).is_none());
+    assert!(queue.get_task(failed_uuid).unwrap().state == TaskState::Queued);
+    assert!(queue.requeue_from_dead_letter(&mut dead_letter, failed_uuid).is_err());
+}
+
+#[test]
+fn highest_priority_task_runs_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+
+    let mut low = task_in_state("low", TaskState::Queued);
+    low.priority = -1;
+    let mut urgent = task_in_state("urgent", TaskState::Queued);
+    urgent.priority = 10;
+    let mut urgent_later = task_in_state("urgent later", TaskState::Queued);
+    urgent_later.priority = 10;
+    urgent_later.request_timestamp = NsTimestamp::from_nanos(urgent.request_timestamp.as_nanos() + 1);
+    let mut done = task_in_state("done", TaskState::Done);
+    done.priority = 100;
+
+    queue.add_task(low).unwrap();
+    queue.add_task(task_in_state("normal", TaskState::Queued)).unwrap();
+    queue.add_task(urgent_later).unwrap();
+    queue.add_task(urgent).unwrap();
+    queue.add_task(done).unwrap();
+
+    let mut order = Vec::new();
+    while let Some(task) = queue.top_queued_task_mut() {
+        order.push(task.name.clone());
+        task.state = TaskState::Done;
+    }
+    assert_eq!(order, vec!["urgent", "urgent later", "normal", "low"]);
+    assert!(queue.top_queued_task().is_none());
+}
+
+#[test]
+fn dependent_task_waits_for_its_dependency() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+
+    let clip = task_in_state("process clip", TaskState::Queued);
+    let clip_uuid = clip.uuid.0;
+    let mut montage = task_in_state("cut montage", TaskState::Queued);
+    montage.depends_on.push(clip.uuid);
+    // the montage would be first otherwise
+    montage.priority = 1;
+    queue.add_task(montage).unwrap();
+    queue.add_task(clip).unwrap();
+
+    let task = queue.top_eligible_task_mut().unwrap();
+    assert_eq!(task.name, "process clip");
+    task.state = TaskState::Working;
+    assert!(queue.top_eligible_task_mut().is_none());
+
+    queue.get_task_mut(clip_uuid).unwrap().state = TaskState::Done;
+    assert_eq!(queue.top_eligible_task_mut().unwrap().name, "cut montage");
+    assert!(queue.tasks_with_failed_dependencies().is_empty());
+}
+
+#[test]
+fn failed_dependency_is_skipped_and_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+
+    let clip = task_in_state("process clip", TaskState::Failed);
+    let mut montage = task_in_state("cut montage", TaskState::Queued);
+    montage.depends_on.push(clip.uuid);
+    montage.priority = 1;
+    let montage_uuid = montage.uuid.0;
+    queue.add_task(clip).unwrap();
+    queue.add_task(montage).unwrap();
+    queue.add_task(task_in_state("unrelated", TaskState::Queued)).unwrap();
+
+    assert_eq!(queue.top_eligible_task_mut().unwrap().name, "unrelated");
+    let blocked = queue.tasks_with_failed_dependencies();
+    assert_eq!(blocked.len(), 1);
+    assert_eq!(blocked[0].uuid.0, montage_uuid);
+}
+
+#[test]
+fn cancelled_task_is_not_picked_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+    let task = task_in_state("queued", TaskState::Queued);
+    let task_uuid = task.uuid.0;
+    queue.add_task(task).unwrap();
+    let mut working = task_in_state("working", TaskState::Working);
+    working.priority = -1;
+    let working_uuid = working.uuid.0;
+    queue.add_task(working).unwrap();
+
+    assert!(queue.cancel_task(task_uuid).unwrap());
+    assert!(!queue.cancel_task(working_uuid).unwrap());
+    assert!(queue.get_task(task_uuid).unwrap().state == TaskState::Cancelled);
+    assert!(queue.top_queued_task().is_none());
+    assert!(queue.top_eligible_task_mut().is_none());
+
+    let mut dead_letter = open_queue(dir.path(), TaskQueue::DEAD_LETTER_FILENAME);
+    assert_eq!(queue.move_failed_to_dead_letter(&mut dead_letter), 1);
+}
+
+#[test]
+fn remove_task_by_uuid() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+    let task = task_in_state("queued", TaskState::Queued);
+    let task_uuid = task.uuid.0;
+    queue.add_task(task).unwrap();
+    queue.add_task(task_in_state("other", TaskState::Queued)).unwrap();
+
+    assert_eq!(queue.remove_task(task_uuid).unwrap().name, "queued");
+    assert!(queue.remove_task(task_uuid).is_none());
+    assert!(queue.get_task(task_uuid).is_none());
+    assert_eq!(queue.top_queued_task().unwrap().name, "other");
+}
+
+#[test]
+fn purge_finished_keeps_unfinished_tasks_and_needed_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+    let needed = task_in_state("needed", TaskState::Done);
+    let mut dependent = task_in_state("dependent", TaskState::Queued);
+    dependent.depends_on.push(needed.uuid);
+    let needed_uuid = needed.uuid.0;
+    queue.add_task(needed).unwrap();
+    queue.add_task(dependent).unwrap();
+    queue.add_task(task_in_state("working", TaskState::Working)).unwrap();
+    queue.add_task(task_in_state("done", TaskState::Done)).unwrap();
+    queue.add_task(task_in_state("failed", TaskState::Failed)).unwrap();
+    queue.add_task(task_in_state("cancelled", TaskState::Cancelled)).unwrap();
+
+    assert_eq!(queue.purge_finished(), 3);
+    assert!(queue.get_task(needed_uuid).is_some());
+    assert_eq!(queue.top_eligible_task_mut().unwrap().name, "dependent");
+    assert_eq!(queue.purge_finished(), 0);
+}
+
+#[test]
+fn filter_and_count_tasks() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut queue = open_queue(dir.path(), TaskQueue::STANDARD_FILENAME);
+    for (name, state, worker_pid) in [
+        ("first", TaskState::Queued, None),
+        ("second", TaskState::Queued, None),
+        ("third", TaskState::Working, Some(100)),
+        ("fourth", TaskState::Done, Some(100)),
+        ("fifth", TaskState::Failed, Some(200)),
+    ] {
+        let mut task = task_in_state(name, state);
+        task.worker_pid = worker_pid;
+        queue.add_task(task).unwrap();
+    }
+
+    let names = |tasks: Vec<&Task>| tasks.iter().map(|task| task.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(queue.tasks_by_state(TaskState::Queued)), vec!["first", "second"]);
+    assert!(queue.tasks_by_state(TaskState::Cancelled).is_empty());
+    assert_eq!(names(queue.tasks_by_worker_pid(100)), vec!["third", "fourth"]);
+    assert!(queue.tasks_by_worker_pid(300).is_empty());
+    assert_eq!(
+        queue.counts_by_state(),
+        HashMap::from([
+            (TaskState::Queued, 2),
+            (TaskState::Working, 1),
+            (TaskState::Done, 1),
+            (TaskState::Failed, 1),
+        ])
+    );
+}
@@ -0,0 +1,80 @@
+use crate::hive::job::{CutRange, Job};
+use crate::hive::task::{Task, TaskState};
+use crate::util::uuid::UuidString;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+fn always_failing_job() -> Job {
+    Job::CutVideo {
+        source_proof_uuid: UuidString::new_v4(),
+        source_path: PathBuf::from("source.mkv"),
+        cut_range: CutRange {
+            start_ms: Some(2000),
+            end_ms: Some(1000),
+        },
+        destination_path: PathBuf::from("destination.mkv"),
+    }
+}
+
+#[test]
+fn failing_task_is_retried_until_max_attempts() {
+    let mut task = Task::new("cut".to_string(), always_failing_job());
+    task.max_attempts = 3;
+
+    let mut runs = 0;
+    while task.state == TaskState::Queued {
+        task.execute();
+        runs += 1;
+        assert!(task.results.as_ref().unwrap()["error"].is_string());
+    }
+
+    assert_eq!(runs, 3);
+    assert_eq!(task.attempts, 3);
+    assert!(task.state == TaskState::Failed);
+    assert!(task.finish_timestamp.is_some());
+}
+
+#[test]
+fn successful_task_is_done_after_one_attempt() {
+    let mut task = Task::new("sleep".to_string(), Job::Sleep { time_nanos: 0 });
+    task.max_attempts = 3;
+    task.execute();
+    assert!(task.state == TaskState::Done);
+    assert_eq!(task.attempts, 1);
+}
+
+#[test]
+fn old_tasks_are_not_retried() {
+    let json = serde_json::to_string(&Task::new("sleep".to_string(), Job::Sleep { time_nanos: 0 })).unwrap();
+    let old_json = json.replace(r#","attempts":0,"max_attempts":1"#, "");
+    assert_ne!(json, old_json);
+    let task: Task = serde_json::from_str(&old_json).unwrap();
+    assert_eq!(task.attempts, 0);
+    assert_eq!(task.max_attempts, 1);
+}
+
+#[test]
+fn task_past_its_deadline_fails() {
+    let mut task = Task::new(
+        "long sleep".to_string(),
+        Job::Sleep {
+            time_nanos: Duration::from_secs(30).as_nanos() as i128,
+        },
+    );
+    task.deadline_nanos = Some(Duration::from_millis(50).as_nanos() as u64);
+
+    let start = Instant::now();
+    task.execute();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(task.state == TaskState::Failed);
+    assert_eq!(task.results.unwrap()["error"], "job did not finish before its deadline");
+}
+
+#[test]
+fn task_within_its_deadline_is_done() {
+    let mut task = Task::new("short sleep".to_string(), Job::Sleep { time_nanos: 1_000_000 });
+    task.deadline_nanos = Some(Duration::from_secs(10).as_nanos() as u64);
+    task.execute();
+    assert!(task.state == TaskState::Done);
+}
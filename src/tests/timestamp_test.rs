@@ -0,0 +1,211 @@
+#[cfg(feature = "timestamp-string-serde")]
+mod string_serde {
+    use crate::util::timestamp::NsTimestamp;
+
+    #[test]
+    fn serializes_as_decimal_string() {
+        let timestamp = NsTimestamp::from_nanos(1_758_412_800_123_456_789);
+        let json = serde_json::to_string(&timestamp).unwrap();
+        assert_eq!(json, "\"1758412800123456789\"");
+    }
+
+    #[test]
+    fn round_trips_values_above_2_pow_53() {
+        let nanos = (1i128 << 53) + 1;
+        let timestamp = NsTimestamp::from_nanos(nanos);
+        let json = serde_json::to_string(&timestamp).unwrap();
+        let deserialized: NsTimestamp = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.as_nanos(), nanos);
+    }
+
+    #[test]
+    fn still_accepts_integers() {
+        let deserialized: NsTimestamp = serde_json::from_str("1758412800123456789").unwrap();
+        assert_eq!(deserialized.as_nanos(), 1_758_412_800_123_456_789);
+    }
+}
+
+mod lenient_deserialization {
+    use crate::util::timestamp::NsTimestamp;
+
+    #[test]
+    fn accepts_floats_truncating_toward_zero() {
+        let deserialized: NsTimestamp = serde_json::from_str("1500000000.9").unwrap();
+        assert_eq!(deserialized.as_nanos(), 1_500_000_000);
+        let deserialized: NsTimestamp = serde_json::from_str("-2.5").unwrap();
+        assert_eq!(deserialized.as_nanos(), -2);
+    }
+
+    #[test]
+    fn accepts_quoted_decimal_strings() {
+        let deserialized: NsTimestamp = serde_json::from_str("\"-1758412800123456789\"").unwrap();
+        assert_eq!(deserialized.as_nanos(), -1_758_412_800_123_456_789);
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        assert!(serde_json::from_str::<NsTimestamp>("\"twelve\"").is_err());
+        assert!(serde_json::from_str::<NsTimestamp>("\"\"").is_err());
+    }
+
+    #[test]
+    fn integers_outside_64_bits_round_trip_exactly() {
+        for nanos in [i128::MAX, i128::MIN, (1i128 << 70) + 1, -((1i128 << 70) + 1)] {
+            let timestamp = NsTimestamp::from_nanos(nanos);
+            let json = serde_json::to_string(&timestamp).unwrap();
+            let deserialized: NsTimestamp = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized.as_nanos(), nanos);
+            let deserialized: NsTimestamp = serde_json::from_reader(json.as_bytes()).unwrap();
+            assert_eq!(deserialized.as_nanos(), nanos);
+        }
+    }
+
+    #[test]
+    fn integers_outside_64_bits_are_exact_inside_structs() {
+        let json = format!("{{\"a\": {}, \"b\": \"5\", \"c\": 2.5}}", (1i128 << 70) + 1);
+        let deserialized: std::collections::HashMap<String, NsTimestamp> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized["a"].as_nanos(), (1i128 << 70) + 1);
+        assert_eq!(deserialized["b"].as_nanos(), 5);
+        assert_eq!(deserialized["c"].as_nanos(), 2);
+    }
+
+    #[test]
+    fn integers_above_i128_are_rejected() {
+        let json = format!("{}", u128::MAX);
+        assert!(serde_json::from_str::<NsTimestamp>(&json).is_err());
+    }
+
+    #[test]
+    fn integers_are_unchanged() {
+        let deserialized: NsTimestamp = serde_json::from_str("1758412800123456789").unwrap();
+        assert_eq!(deserialized.as_nanos(), 1_758_412_800_123_456_789);
+        let deserialized: NsTimestamp = serde_json::from_str("-5").unwrap();
+        assert_eq!(deserialized.as_nanos(), -5);
+    }
+}
+
+mod truncation_and_rounding {
+    use crate::util::timestamp::NsTimestamp;
+
+    #[test]
+    fn truncate_to_secs_floors() {
+        let cases = [
+            (0, 0),
+            (1_999_999_999, 1_000_000_000),
+            (2_000_000_000, 2_000_000_000),
+            (-1, -1_000_000_000),
+            (-1_000_000_000, -1_000_000_000),
+            (-1_000_000_001, -2_000_000_000),
+        ];
+        for (nanos, expected) in cases {
+            assert_eq!(NsTimestamp::from_nanos(nanos).truncate_to_secs().as_nanos(), expected, "{nanos}");
+        }
+    }
+
+    #[test]
+    fn truncate_to_millis_floors() {
+        let cases = [(1_999_999, 1_000_000), (-1, -1_000_000), (-1_000_001, -2_000_000)];
+        for (nanos, expected) in cases {
+            assert_eq!(NsTimestamp::from_nanos(nanos).truncate_to_millis().as_nanos(), expected, "{nanos}");
+        }
+    }
+
+    #[test]
+    fn round_to_secs_ties_away_from_zero() {
+        let cases = [
+            (0, 0),
+            (1_499_999_999, 1_000_000_000),
+            (1_500_000_000, 2_000_000_000),
+            (-1_499_999_999, -1_000_000_000),
+            (-1_500_000_000, -2_000_000_000),
+            (-400_000_000, 0),
+        ];
+        for (nanos, expected) in cases {
+            assert_eq!(NsTimestamp::from_nanos(nanos).round_to_secs().as_nanos(), expected, "{nanos}");
+        }
+    }
+}
+
+mod elapsed {
+    use crate::util::timestamp::NsTimestamp;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn elapsed_is_positive_after_sleeping() {
+        let start = NsTimestamp::now();
+        sleep(Duration::from_millis(5));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= 5_000_000, "{elapsed}");
+        assert!(elapsed < 10_000_000_000, "{elapsed}");
+    }
+
+    #[test]
+    fn duration_since_is_signed() {
+        let earlier = NsTimestamp::from_secs(10);
+        let later = NsTimestamp::from_secs(12);
+        assert_eq!(later.duration_since(earlier), 2_000_000_000);
+        assert_eq!(earlier.duration_since(later), -2_000_000_000);
+    }
+}
+
+mod clamping {
+    use crate::util::timestamp::NsTimestamp;
+
+    #[test]
+    fn clamp_below_inside_and_above() {
+        let min = NsTimestamp::from_secs(-10);
+        let max = NsTimestamp::from_secs(10);
+        assert_eq!(NsTimestamp::from_secs(-20).clamp(min, max), min);
+        assert_eq!(NsTimestamp::from_secs(-5).clamp(min, max), NsTimestamp::from_secs(-5));
+        assert_eq!(NsTimestamp::from_secs(5).clamp(min, max), NsTimestamp::from_secs(5));
+        assert_eq!(NsTimestamp::from_secs(20).clamp(min, max), max);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clamp_panics_on_reversed_range() {
+        NsTimestamp::from_secs(0).clamp(NsTimestamp::from_secs(10), NsTimestamp::from_secs(-10));
+    }
+
+    #[test]
+    fn min_and_max() {
+        let earlier = NsTimestamp::from_nanos(-1);
+        let later = NsTimestamp::from_nanos(1);
+        assert_eq!(NsTimestamp::min(earlier, later), earlier);
+        assert_eq!(later.max(earlier), later);
+    }
+}
+
+mod mixed_forms {
+    use crate::util::file_ex::FileEx;
+    use crate::util::timestamp::NsTimestamp;
+    use serde::Deserialize;
+    use std::fs;
+
+    #[derive(Deserialize)]
+    struct Record {
+        timestamp: NsTimestamp,
+    }
+
+    #[test]
+    fn accepts_rfc3339_strings() {
+        let deserialized: NsTimestamp = serde_json::from_str("\"1970-01-01T00:00:01.5+00:00\"").unwrap();
+        assert_eq!(deserialized.as_nanos(), 1_500_000_000);
+    }
+
+    #[test]
+    fn reads_jsonlines_with_mixed_forms() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mixed.jsonl");
+        fs::write(
+            &path,
+            "{\"timestamp\":1758412800000000001}\n{\"timestamp\":\"1758412800000000002\"}\n{\"timestamp\":\"2025-09-21T00:00:00.000000003Z\"}\n",
+        )
+        .unwrap();
+
+        let records: Vec<Record> = path.read_from_jsonlines().unwrap().unwrap();
+        let nanos: Vec<i128> = records.iter().map(|record| record.timestamp.as_nanos()).collect();
+        assert_eq!(nanos, vec![1_758_412_800_000_000_001, 1_758_412_800_000_000_002, 1_758_412_800_000_000_003]);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::util::uuid::UuidString;
+
+#[test]
+fn invalid_uuid_is_an_error() {
+    let result = serde_json::from_str::<UuidString>("\"not-a-uuid\"");
+    assert!(result.is_err());
+}
+
+#[test]
+fn valid_uuid_deserializes() {
+    let uuid: UuidString = serde_json::from_str("\"67e55044-10b1-426f-9247-bb680e5fe0c8\"").unwrap();
+    assert_eq!(uuid.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+}
+
+#[test]
+fn equal_uuids_hash_the_same() {
+    let uuid = uuid::Uuid::new_v4();
+    let set: std::collections::HashSet<UuidString> = [UuidString(uuid), UuidString(uuid)].into_iter().collect();
+    assert_eq!(set.len(), 1);
+}
+
+#[test]
+fn parses_from_str() {
+    let uuid: UuidString = "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap();
+    assert_eq!(uuid.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    assert!("not-a-uuid".parse::<UuidString>().is_err());
+}
+
+#[test]
+fn default_is_nil() {
+    assert!(UuidString::default().0.is_nil());
+}
+
+#[test]
+fn new_v4_and_nil_constructors() {
+    assert_ne!(UuidString::new_v4(), UuidString::new_v4());
+    assert_eq!(UuidString::nil().0.as_bytes(), &[0u8; 16]);
+}
@@ -1,7 +1,12 @@
+use flate2::Compression;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
 use serde::{Deserialize, Serialize};
-use std::fs::{self};
-use std::path::Path;
-use std::{io, result};
+use serde_jsonlines::{JsonLinesReader, JsonLinesWriter};
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::{io, process, result};
 
 #[derive(Debug)]
 pub enum Error {
@@ -11,10 +16,30 @@ pub enum Error {
     CannotSerializeJSON(serde_json::Error),
     CannotDeserializeJSONLines(io::Error),
     CannotSerializeJSONLines(io::Error),
+    CannotDeserializeTOML(toml::de::Error),
+    CannotSerializeTOML(toml::ser::Error),
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// List of `(line_number, raw_line)` pairs of lines that could not be deserialized, see [`FileEx::read_from_jsonlines_lenient`].
+pub type MalformedLines = Vec<(usize, String)>;
+
+/// The first two bytes of every gzip file.
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
+/// Get the path of the temporary file used when atomically writing to `path`.
+///
+/// The temporary file is a sibling of the target file, called `<name>.tmp.<pid>`, so that it is on the same filesystem as the target.
+pub fn atomic_temp_path_for(path: &Path) -> io::Result<PathBuf> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no filename"))?;
+    let mut temp_filename = filename.to_os_string();
+    temp_filename.push(format!(".tmp.{}", process::id()));
+    Ok(path.with_file_name(temp_filename))
+}
+
 pub trait FileEx {
     fn file_path(&self) -> &Path;
 
@@ -41,6 +66,16 @@ pub trait FileEx {
         }
     }
 
+    fn read_from_toml<D: for<'a> Deserialize<'a>>(&self) -> Result<Option<D>> {
+        let content = self.read_to_string().map_err(Error::CannotReadFile)?;
+        if let Some(toml) = content {
+            let deserialized = toml::from_str(&toml).map_err(Error::CannotDeserializeTOML)?;
+            Ok(Some(deserialized))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn read_from_jsonlines<D: for<'a> Deserialize<'a>>(&self) -> Result<Option<Vec<D>>> {
         let result = serde_jsonlines::json_lines(self.file_path());
         match result {
@@ -57,6 +92,48 @@ pub trait FileEx {
         }
     }
 
+    /// Read a jsonlines file, skipping lines that cannot be deserialized.
+    ///
+    /// Unlike [`Self::read_from_jsonlines`], a single malformed line does not make the whole read fail.
+    /// Instead, this function returns the successfully deserialized records, and a list of `(line_number, raw_line)` pairs
+    /// for every line that could not be deserialized. Line numbers start at 1. Empty lines are ignored.
+    fn read_from_jsonlines_lenient<D: for<'a> Deserialize<'a>>(&self) -> Result<Option<(Vec<D>, MalformedLines)>> {
+        let Some(content) = self.read_to_string().map_err(Error::CannotReadFile)? else {
+            return Ok(None);
+        };
+        let mut records = Vec::new();
+        let mut malformed_lines = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(_) => malformed_lines.push((i + 1, line.to_string())),
+            }
+        }
+        Ok(Some((records, malformed_lines)))
+    }
+
+    /// Read a jsonlines file which may be compressed with gzip.
+    ///
+    /// The file is treated as gzip-compressed if its name ends with `.gz`, or if it starts with the gzip magic bytes.
+    /// Otherwise, it is read the same way as with [`Self::read_from_jsonlines`].
+    fn read_from_jsonlines_maybe_gz<D: for<'a> Deserialize<'a>>(&self) -> Result<Option<Vec<D>>> {
+        let bytes = match fs::read(self.file_path()) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::CannotReadFile(e)),
+        };
+        let is_gz = self.file_path().extension().is_some_and(|extension| extension == "gz") || bytes.starts_with(&GZIP_MAGIC_BYTES);
+        let records: io::Result<Vec<D>> = if is_gz {
+            JsonLinesReader::new(BufReader::new(GzDecoder::new(bytes.as_slice()))).read_all().collect()
+        } else {
+            JsonLinesReader::new(bytes.as_slice()).read_all().collect()
+        };
+        Ok(Some(records.map_err(Error::CannotDeserializeJSONLines)?))
+    }
+
     fn write<C: AsRef<[u8]>>(&self, contents: C) -> io::Result<()> {
         fs::write(self.file_path(), contents)
     }
@@ -73,10 +150,78 @@ pub trait FileEx {
         Ok(())
     }
 
+    fn write_as_toml<S: Serialize>(&self, serializable: S) -> Result<()> {
+        let toml = toml::to_string_pretty(&serializable).map_err(Error::CannotSerializeTOML)?;
+        self.write(&toml).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
+
     fn write_as_jsonlines<S: Serialize>(&self, serializable: &[S]) -> Result<()> {
         serde_jsonlines::write_json_lines(self.file_path(), serializable).map_err(Error::CannotSerializeJSONLines)?;
         Ok(())
     }
+
+    /// Append a single record to the end of a jsonlines file, without rewriting the rest of the file.
+    ///
+    /// The file is created if it doesn't exist yet.
+    fn append_jsonline<S: Serialize>(&self, record: &S) -> Result<()> {
+        let mut line = serde_json::to_vec(record).map_err(|e| Error::CannotSerializeJSONLines(e.into()))?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file_path())
+            .map_err(Error::CannotWriteFile)?;
+        file.write_all(&line).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
+
+    /// Write a jsonlines file compressed with gzip.
+    ///
+    /// The file can be read back with [`Self::read_from_jsonlines_maybe_gz`].
+    fn write_as_jsonlines_gz<S: Serialize>(&self, serializable: &[S]) -> Result<()> {
+        let mut writer = JsonLinesWriter::new(GzEncoder::new(Vec::new(), Compression::default()));
+        writer.write_all(serializable).map_err(Error::CannotSerializeJSONLines)?;
+        let compressed = writer.into_inner().finish().map_err(Error::CannotSerializeJSONLines)?;
+        self.write(compressed).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
+
+    /// Write to the file atomically.
+    ///
+    /// The contents are first written to a temporary file next to the target file (see [`atomic_temp_path_for`]),
+    /// which is then renamed over the target file. Renaming is atomic on the same filesystem,
+    /// so if the process dies in the middle of writing, the original file is left intact instead of being truncated.
+    fn write_atomic<C: AsRef<[u8]>>(&self, contents: C) -> io::Result<()> {
+        let temp_path = atomic_temp_path_for(self.file_path())?;
+        let result = fs::write(&temp_path, contents).and_then(|()| fs::rename(&temp_path, self.file_path()));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn write_as_json_atomic<S: Serialize>(&self, serializable: S) -> Result<()> {
+        let json = serde_json::to_string(&serializable).map_err(Error::CannotSerializeJSON)?;
+        self.write_atomic(&json).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
+
+    fn write_as_json_pretty_atomic<S: Serialize>(&self, serializable: S) -> Result<()> {
+        let json = serde_json::to_string_pretty(&serializable).map_err(Error::CannotSerializeJSON)?;
+        self.write_atomic(&json).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
+
+    fn write_as_jsonlines_atomic<S: Serialize>(&self, serializable: &[S]) -> Result<()> {
+        let mut jsonlines = Vec::new();
+        for value in serializable {
+            serde_json::to_writer(&mut jsonlines, value).map_err(|e| Error::CannotSerializeJSONLines(e.into()))?;
+            jsonlines.push(b'\n');
+        }
+        self.write_atomic(&jsonlines).map_err(Error::CannotWriteFile)?;
+        Ok(())
+    }
 }
 
 impl FileEx for Path {
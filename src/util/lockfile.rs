@@ -3,12 +3,15 @@ use crate::util::file_ex::{self, FileEx};
 use crate::util::lockfile::{self};
 use crate::util::timestamp::NsTimestamp;
 use notify::{ErrorKind, Event, RecursiveMode, Watcher};
+use serde::Serialize;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::id;
 use std::result;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum Error {
@@ -18,9 +21,12 @@ pub enum Error {
     CannotCreateLockfile(io::Error),
     CannotWriteLockfile(io::Error),
     CannotRemoveLockfile(io::Error),
+    CannotReadLockfile(io::Error),
+    MalformedLockfile,
     CannotGetRecommendedWatcher(notify::Error),
     CannotWatchLockfile(notify::Error),
     FileExError(file_ex::Error),
+    Timeout,
 }
 
 impl Error {
@@ -40,6 +46,20 @@ impl From<file_ex::Error> for Error {
 
 pub type Result<T> = result::Result<T, Error>;
 
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable printing `[lockfile]` debug messages on stderr for the whole process.
+///
+/// This is disabled by default. Warnings are printed regardless of this setting.
+pub fn set_lockfile_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Are `[lockfile]` debug messages printed on stderr? See [`set_lockfile_verbose`].
+pub fn lockfile_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
 pub fn is_file_locked<T>(result: &Result<T>) -> bool {
     if let Err(error) = result {
         error.is_already_locked()
@@ -48,6 +68,71 @@ pub fn is_file_locked<T>(result: &Result<T>) -> bool {
     }
 }
 
+/// Information about the process holding a lock, stored inside of the lockfile.
+///
+/// The lockfile consists of `key: value` lines, preceded by a few comment lines starting with `#`:
+/// ```text
+/// # File locked by scoretracker v0.1.0
+/// # WARNING - Do not edit the locked file. Editing the locked file may result in data loss.
+/// version: 0.1.0
+/// pid: 1234
+/// lock_timestamp: 1758412800000000000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockfileInfo {
+    /// Version of scoretracker that created the lockfile.
+    pub version: String,
+
+    /// PID of the process that created the lockfile.
+    pub pid: u32,
+
+    /// When was the lock acquired?
+    pub lock_timestamp: NsTimestamp,
+}
+
+impl LockfileInfo {
+    /// Generate the contents of a lockfile containing this information.
+    pub fn to_lockfile_contents(&self) -> String {
+        let Self {
+            version,
+            pid,
+            lock_timestamp,
+        } = self;
+        format!(
+            "# File locked by scoretracker v{version}\n# WARNING - Do not edit the locked file. Editing the locked file may result in data loss.\nversion: {version}\npid: {pid}\nlock_timestamp: {}\n",
+            lock_timestamp.as_nanos()
+        )
+    }
+
+    /// Parse the contents of a lockfile.
+    ///
+    /// Returns [`None`] if any of the fields is missing or invalid. Comment lines, empty lines and unknown keys are ignored.
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut version = None;
+        let mut pid = None;
+        let mut lock_timestamp = None;
+        for line in contents.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(": ") else {
+                continue;
+            };
+            match key {
+                "version" => version = Some(value.to_string()),
+                "pid" => pid = Some(value.parse().ok()?),
+                "lock_timestamp" => lock_timestamp = Some(NsTimestamp::from_nanos(value.parse().ok()?)),
+                _ => {}
+            }
+        }
+        Some(Self {
+            version: version?,
+            pid: pid?,
+            lock_timestamp: lock_timestamp?,
+        })
+    }
+}
+
 /// A lockfile handle.
 ///
 /// A lockfile is like a warning to other programs and processes, that says - if you write to this file, your changes will most likely be lost.
@@ -76,14 +161,13 @@ pub struct LockfileHandle {
 }
 
 impl LockfileHandle {
-    const VERBOSE: bool = true;
-
     fn generate_lockfile_contents() -> String {
-        let timestamp = NsTimestamp::now();
-        let pid = id();
-        format!(
-            "File locked by scoretracker v{VERSION}\nPID: {pid}\nLock timestamp: {timestamp}\n\nWARNING - Do not edit the locked file. Editing the locked file may result in data loss.\n"
-        )
+        LockfileInfo {
+            version: VERSION.to_string(),
+            pid: id(),
+            lock_timestamp: NsTimestamp::now(),
+        }
+        .to_lockfile_contents()
     }
 
     fn create_lockfile_on_disk(lockfile_path: &Path) -> Result<()> {
@@ -92,7 +176,7 @@ impl LockfileHandle {
             .write_all(Self::generate_lockfile_contents().as_bytes())
             .map_err(Error::CannotWriteLockfile)?;
 
-        if Self::VERBOSE {
+        if lockfile_verbose() {
             eprintln!("[lockfile] created lockfile: {:?}", lockfile_path);
         }
         Ok(())
@@ -166,11 +250,28 @@ impl LockfileHandle {
     /// If the path for the lockfile cannot be generated, this function may return [`Error::NoParentPath`], [`Error::NoFilename`], or [`Error::FilenameIsNotUTF8`].
     /// If the lockfile could not be written to, this function will return [`Error::CannotWriteLockfile`].
     pub fn acquire_wait<P: AsRef<Path>>(path: P) -> Result<LockfileHandle> {
+        Self::acquire_wait_until(path, None)
+    }
+
+    /// Wait until the file can be safely opened to be updated, but give up after `timeout`.
+    ///
+    /// This works like [`Self::acquire_wait`], except that if the lock could not be acquired within `timeout`, [`Error::Timeout`] is returned.
+    ///
+    /// # Errors
+    /// See [`Self::acquire_wait`]. Additionally, this function will return [`Error::Timeout`] if the lock was not acquired in time.
+    pub fn acquire_wait_timeout<P: AsRef<Path>>(path: P, timeout: Duration) -> Result<LockfileHandle> {
+        Self::acquire_wait_until(path, Some(Instant::now() + timeout))
+    }
+
+    /// Implementation of [`Self::acquire_wait`] and [`Self::acquire_wait_timeout`].
+    ///
+    /// If `deadline` is [`None`], this function waits forever.
+    fn acquire_wait_until<P: AsRef<Path>>(path: P, deadline: Option<Instant>) -> Result<LockfileHandle> {
         // Try to create initial lockfile
         let initial_result = Self::acquire(&path);
         if !is_file_locked(&initial_result) {
             // The file was not locked before - return the initial result, whatever it was.
-            if Self::VERBOSE {
+            if lockfile_verbose() {
                 eprintln!("[lockfile] acquire_wait: file not locked from initial result");
             }
             return initial_result;
@@ -190,18 +291,40 @@ impl LockfileHandle {
         let result = Self::acquire(&path);
         if !is_file_locked(&result) {
             // The file is not locked anymore! - return the result, whatever it was.
-            if Self::VERBOSE {
+            if lockfile_verbose() {
                 eprintln!("[lockfile] acquire_wait: file unlocked from after setup");
             }
             return result;
         }
 
-        if Self::VERBOSE {
+        if lockfile_verbose() {
             eprintln!("[lockfile] acquire_wait: waiting for file to be unlocked...");
         }
-        for res in rx {
+        loop {
+            // events can keep arriving before the timeout of `recv_timeout` runs out, so the deadline has to be checked on every iteration
+            if let Some(deadline) = deadline
+                && Instant::now() >= deadline
+            {
+                if lockfile_verbose() {
+                    eprintln!("[lockfile] acquire_wait: timed out waiting for file to be unlocked");
+                }
+                return Err(Error::Timeout);
+            }
+            let res = match deadline {
+                None => rx.recv().expect("the watcher should never stop sending events while it exists"),
+                Some(deadline) => match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(res) => res,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if lockfile_verbose() {
+                            eprintln!("[lockfile] acquire_wait: timed out waiting for file to be unlocked");
+                        }
+                        return Err(Error::Timeout);
+                    }
+                    Err(RecvTimeoutError::Disconnected) => unreachable!("the watcher should never stop sending events while it exists"),
+                },
+            };
             let event = res.unwrap();
-            if Self::VERBOSE {
+            if lockfile_verbose() {
                 eprintln!("[lockfile] acquire_wait: event about lockfile: {event:?}");
             }
 
@@ -212,7 +335,7 @@ impl LockfileHandle {
                 let result = Self::acquire(&path);
                 if !is_file_locked(&result) {
                     // The file is not locked anymore - return the result, whatever it was.
-                    if Self::VERBOSE {
+                    if lockfile_verbose() {
                         eprintln!("[lockfile] acquire_wait: file unlocked from notification");
                     }
                     return result;
@@ -237,27 +360,106 @@ impl LockfileHandle {
                 }
             });
 
-            if Self::VERBOSE {
+            if lockfile_verbose() {
                 eprintln!("[lockfile] acquire_wait: rewatching lockfile");
             }
             watcher
                 .watch(&lockfile_path, RecursiveMode::NonRecursive)
                 .map_err(Error::CannotWatchLockfile)?; // TODO: this will sometimes exit if the file doesn't exist anymore as you can't watch paths that don't exist. should be very rare though.
         }
+    }
+
+    /// Wait until the file can be safely opened to be updated, reclaiming the lock if its holder is dead.
+    ///
+    /// This works like [`Self::acquire_wait`], except that if the file is already locked, the PID recorded in the existing lockfile is checked first.
+    /// If no process with that PID is running, the lockfile is assumed to be left behind by a crashed process - it is removed and the lock is acquired again.
+    ///
+    /// This is only safe if the lockfile was created by a process on the same machine, as PIDs from other machines cannot be checked.
+    /// If the PID cannot be read from the lockfile, or liveness of processes cannot be checked on this platform, the holder is assumed to be alive.
+    ///
+    /// # Errors
+    /// See [`Self::acquire_wait`]. Additionally, if a stale lockfile could not be removed, this function will return [`Error::CannotRemoveLockfile`].
+    pub fn acquire_wait_reclaim_stale<P: AsRef<Path>>(path: P) -> Result<LockfileHandle> {
+        let initial_result = Self::acquire(&path);
+        if !is_file_locked(&initial_result) {
+            return initial_result;
+        }
+
+        let lockfile_path = Self::lockfile_path_for(&path)?;
+        if let Ok(Some(LockfileInfo { pid, .. })) = Self::read_holder_info(&path)
+            && !is_process_alive(pid)
+        {
+            eprintln!("warning: reclaiming stale lockfile at {lockfile_path:?} held by dead process {pid}");
+            Self::force_unlock(&path)?;
+        }
+
+        Self::acquire_wait(path)
+    }
+
+    /// Read information about the process currently holding the lock on a file.
+    ///
+    /// This reads the lockfile of the file at `path` (not the lockfile path itself), and returns [`None`] if the file is not locked.
+    ///
+    /// # Errors
+    /// If the path for the lockfile cannot be generated, this function may return [`Error::NoParentPath`], [`Error::NoFilename`], or [`Error::FilenameIsNotUTF8`].
+    /// If the lockfile could not be read, this function will return [`Error::CannotReadLockfile`].
+    /// If the contents of the lockfile could not be parsed, this function will return [`Error::MalformedLockfile`].
+    pub fn read_holder_info<P: AsRef<Path>>(path: P) -> Result<Option<LockfileInfo>> {
+        let lockfile_path = Self::lockfile_path_for(path)?;
+        let Some(contents) = lockfile_path.read_to_string().map_err(Error::CannotReadLockfile)? else {
+            return Ok(None);
+        };
+        let info = LockfileInfo::parse(&contents).ok_or(Error::MalformedLockfile)?;
+        Ok(Some(info))
+    }
 
-        unreachable!();
+    /// Remove the lockfile of a file, without owning its [`LockfileHandle`].
+    ///
+    /// This is meant for recovery tools, to clear lockfiles left behind by crashed processes.
+    /// Removing a lockfile that is still in use by another process defeats the purpose of locking, and may result in data loss.
+    ///
+    /// Returns `Ok(())` if the file was not locked in the first place.
+    ///
+    /// # Errors
+    /// If the path for the lockfile cannot be generated, this function may return [`Error::NoParentPath`], [`Error::NoFilename`], or [`Error::FilenameIsNotUTF8`].
+    /// If the lockfile exists but could not be removed, this function will return [`Error::CannotRemoveLockfile`].
+    pub fn force_unlock<P: AsRef<Path>>(path: P) -> Result<()> {
+        let lockfile_path = Self::lockfile_path_for(path)?;
+        match fs::remove_file(&lockfile_path) {
+            Ok(()) => {
+                if lockfile_verbose() {
+                    eprintln!("[lockfile] force unlocked: {lockfile_path:?}");
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::CannotRemoveLockfile(e)),
+        }
     }
 
     pub fn unlock(self) -> lockfile::Result<()> {
         fs::remove_file(&self.lockfile_path).map_err(Error::CannotRemoveLockfile)?;
 
-        if Self::VERBOSE {
+        if lockfile_verbose() {
             eprintln!("[lockfile] unlocked manually: {:?}", &self.lockfile_path);
         }
         Ok(())
     }
 }
 
+/// Check if a process with the given PID is running on this machine.
+///
+/// On platforms where this cannot be checked, the process is always assumed to be alive.
+#[cfg(target_os = "linux")]
+fn is_process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_process_alive(_pid: u32) -> bool {
+    true
+}
+
 impl Drop for LockfileHandle {
     fn drop(&mut self) {
         if let Err(e) = fs::remove_file(&self.lockfile_path) {
@@ -267,14 +469,23 @@ impl Drop for LockfileHandle {
             );
             return;
         }
-        if Self::VERBOSE {
+        if lockfile_verbose() {
             eprintln!("[lockfile] unlocked by dropping: {:?}", &self.lockfile_path);
         }
     }
 }
 
+/// Files guarded by a lockfile are always written atomically, see [`FileEx::write_atomic`].
 impl FileEx for LockfileHandle {
     fn file_path(&self) -> &Path {
         &self.main_file_path
     }
+
+    fn write<C: AsRef<[u8]>>(&self, contents: C) -> io::Result<()> {
+        self.write_atomic(contents)
+    }
+
+    fn write_as_jsonlines<S: Serialize>(&self, serializable: &[S]) -> file_ex::Result<()> {
+        self.write_as_jsonlines_atomic(serializable)
+    }
 }
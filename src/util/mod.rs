@@ -3,5 +3,6 @@ pub mod error;
 pub mod file_ex;
 pub mod lockfile;
 pub mod log;
+pub mod path;
 pub mod timestamp;
 pub mod uuid;
@@ -0,0 +1,41 @@
+use std::io;
+use std::path::{Component, Path};
+
+#[derive(Debug)]
+pub enum PathError {
+    CannotCanonicalizeRoot(io::Error),
+    CannotCanonicalizeFile(io::Error),
+    OutsideOfRoot,
+    PathIsNotUTF8,
+}
+
+/// Get the path of a file relative to the library root directory.
+///
+/// Both paths are canonicalized first, so `..` components and symlinks are resolved, and the file has to exist on disk.
+/// The returned path always uses forward slashes as separators (for example `subdir/video.mkv`) regardless of the platform,
+/// so that it can be used in library URLs and compared between machines.
+///
+/// # Errors
+/// This function will return:
+/// - [`PathError::CannotCanonicalizeRoot`] or [`PathError::CannotCanonicalizeFile`] if either path cannot be canonicalized (for example when it doesn't exist),
+/// - [`PathError::OutsideOfRoot`] if the file is not inside of the root directory,
+/// - [`PathError::PathIsNotUTF8`] if the relative path is not valid UTF-8.
+pub fn to_library_relative(root: &Path, file: &Path) -> Result<String, PathError> {
+    let root = root.canonicalize().map_err(PathError::CannotCanonicalizeRoot)?;
+    let file = file.canonicalize().map_err(PathError::CannotCanonicalizeFile)?;
+    let relative = file.strip_prefix(&root).map_err(|_| PathError::OutsideOfRoot)?;
+
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().ok_or(PathError::PathIsNotUTF8)?),
+            // Canonicalized paths should never contain other components, but reject them just in case.
+            _ => return Err(PathError::OutsideOfRoot),
+        }
+    }
+    if parts.is_empty() {
+        // The root directory itself is not a file inside of the root.
+        return Err(PathError::OutsideOfRoot);
+    }
+    Ok(parts.join("/"))
+}
@@ -1,6 +1,7 @@
 use crate::util::error::SystemTimeConversionError;
 use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
-use serde::{Deserialize, Serialize, de::Visitor};
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::num::TryFromIntError;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -15,6 +16,13 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 /// This type is serialized as [`i128`] when used with serde.
 ///
 /// This type can be deserialized from any integer value, although if the integer is larger than [`i128::MAX`] then the conversion will fail.
+/// It can also be deserialized from a floating-point number of nanoseconds (truncated toward zero), from a string containing a decimal integer of nanoseconds,
+/// or from an RFC 3339 date string (for example `"2025-09-21T00:00:00.5Z"`). Integers always remain the canonical output.
+/// Integers are always read exactly, including in JSON, where integers that do not fit in 64 bits would otherwise be read as floating-point numbers.
+///
+/// With the `timestamp-string-serde` feature enabled, this type is serialized as a decimal string instead (for example `"1758412800000000000"`),
+/// because JavaScript cannot represent integers larger than 2^53 without losing precision.
+/// Deserialization works the same way with or without the feature, so files written in either mode can always be read.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NsTimestamp(i128);
 
@@ -161,6 +169,90 @@ impl NsTimestamp {
         Self::from_date_time(date_time)
     }
 
+    /// Get the amount of nanoseconds that have passed since this timestamp, according to [`NsTimestamp::now`].
+    ///
+    /// The result is negative if this timestamp is in the future.
+    pub fn elapsed(self) -> i128 {
+        NsTimestamp::now().duration_since(self)
+    }
+
+    /// Get the amount of nanoseconds that have passed from `earlier` to this timestamp.
+    ///
+    /// The result is negative if `earlier` is actually later than this timestamp.
+    pub fn duration_since(self, earlier: Self) -> i128 {
+        self.0 - earlier.0
+    }
+
+    /// Restrict the timestamp to the `[min, max]` range.
+    ///
+    /// This is the same as [`Ord::clamp`].
+    ///
+    /// # Panics
+    /// This function will panic if `min > max`.
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        Ord::clamp(self, min, max)
+    }
+
+    /// Get the earlier of two timestamps.
+    pub fn min(self, other: Self) -> Self {
+        Ord::min(self, other)
+    }
+
+    /// Get the later of two timestamps.
+    pub fn max(self, other: Self) -> Self {
+        Ord::max(self, other)
+    }
+
+    /// Truncate the timestamp to a whole second.
+    ///
+    /// Truncation always rounds toward negative infinity, so timestamps before [`UNIX_EPOCH`] are moved further into the past.
+    ///
+    /// | nanoseconds      | result           |
+    /// |------------------|------------------|
+    /// | `0`              | `0`              |
+    /// | `1_999_999_999`  | `1_000_000_000`  |
+    /// | `2_000_000_000`  | `2_000_000_000`  |
+    /// | `-1`             | `-1_000_000_000` |
+    /// | `-1_000_000_000` | `-1_000_000_000` |
+    /// | `-1_000_000_001` | `-2_000_000_000` |
+    pub fn truncate_to_secs(self) -> Self {
+        Self(self.0.div_euclid(1_000_000_000i128) * 1_000_000_000i128)
+    }
+
+    /// Truncate the timestamp to a whole millisecond.
+    ///
+    /// Truncation always rounds toward negative infinity, so timestamps before [`UNIX_EPOCH`] are moved further into the past.
+    ///
+    /// | nanoseconds  | result       |
+    /// |--------------|--------------|
+    /// | `0`          | `0`          |
+    /// | `1_999_999`  | `1_000_000`  |
+    /// | `2_000_000`  | `2_000_000`  |
+    /// | `-1`         | `-1_000_000` |
+    /// | `-1_000_000` | `-1_000_000` |
+    /// | `-1_000_001` | `-2_000_000` |
+    pub fn truncate_to_millis(self) -> Self {
+        Self(self.0.div_euclid(1_000_000i128) * 1_000_000i128)
+    }
+
+    /// Round the timestamp to the nearest whole second.
+    ///
+    /// Ties (exactly half a second) are rounded away from zero.
+    ///
+    /// | nanoseconds      | result           |
+    /// |------------------|------------------|
+    /// | `0`              | `0`              |
+    /// | `1_499_999_999`  | `1_000_000_000`  |
+    /// | `1_500_000_000`  | `2_000_000_000`  |
+    /// | `-1_499_999_999` | `-1_000_000_000` |
+    /// | `-1_500_000_000` | `-2_000_000_000` |
+    pub fn round_to_secs(self) -> Self {
+        let secs = self.0 / 1_000_000_000i128;
+        let rem = self.0 % 1_000_000_000i128;
+        let rounded_secs = if rem.abs() * 2 >= 1_000_000_000i128 { secs + rem.signum() } else { secs };
+        Self(rounded_secs * 1_000_000_000i128)
+    }
+
     pub fn to_date_time_string_utc(self) -> String {
         self.as_date_time_utc().to_rfc3339_opts(SecondsFormat::Nanos, true)
     }
@@ -170,6 +262,61 @@ impl NsTimestamp {
     }
 }
 
+/// Format an amount of nanoseconds as a compact, human-readable duration.
+///
+/// Only non-zero units are shown. Milliseconds are shown when the duration is not a whole amount of seconds,
+/// and durations shorter than a millisecond are shown in nanoseconds.
+///
+/// ```
+/// use scoretracker_core::util::timestamp::format_duration_ns;
+///
+/// assert_eq!(format_duration_ns(0), "0s");
+/// assert_eq!(format_duration_ns(1_500_000_000), "1s 500ms");
+/// assert_eq!(format_duration_ns(3_723_000_000_000), "1h 2m 3s");
+/// assert_eq!(format_duration_ns(-90_000_000_000), "-1m 30s");
+/// assert_eq!(format_duration_ns(250), "250ns");
+/// ```
+pub fn format_duration_ns(ns: i128) -> String {
+    const UNITS: [(u128, &str); 5] = [
+        (86_400_000_000_000, "d"),
+        (3_600_000_000_000, "h"),
+        (60_000_000_000, "m"),
+        (1_000_000_000, "s"),
+        (1_000_000, "ms"),
+    ];
+
+    let sign = if ns < 0 { "-" } else { "" };
+    let mut remaining = ns.unsigned_abs();
+    if remaining == 0 {
+        return "0s".to_string();
+    }
+    if remaining < 1_000_000 {
+        return format!("{sign}{remaining}ns");
+    }
+
+    let mut parts = Vec::new();
+    for (unit_nanos, unit_name) in UNITS {
+        let amount = remaining / unit_nanos;
+        remaining %= unit_nanos;
+        if amount > 0 {
+            parts.push(format!("{amount}{unit_name}"));
+        }
+    }
+    format!("{sign}{}", parts.join(" "))
+}
+
+/// Format a [`Duration`] as a compact, human-readable duration. See [`format_duration_ns`] for details.
+///
+/// ```
+/// use scoretracker_core::util::timestamp::format_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(format_duration(Duration::from_millis(134_250)), "2m 14s 250ms");
+/// ```
+pub fn format_duration(duration: Duration) -> String {
+    format_duration_ns(duration.as_nanos().try_into().unwrap_or(i128::MAX))
+}
+
 impl fmt::Display for NsTimestamp {
     /// Display a [`NsTimestamp`] as a UTC datetime string, and the amount of nanoseconds since [`UNIX_EPOCH`].
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -263,9 +410,15 @@ impl TryInto<SystemTime> for NsTimestamp {
 }
 
 impl Serialize for NsTimestamp {
+    #[cfg(not(feature = "timestamp-string-serde"))]
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         serializer.serialize_i128(self.0)
     }
+
+    #[cfg(feature = "timestamp-string-serde")]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
 }
 
 struct NanosecondTimestampVisitor;
@@ -274,7 +427,7 @@ impl<'de> Visitor<'de> for NanosecondTimestampVisitor {
     type Value = NsTimestamp;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a number of nanoseconds since 1970-01-01 (can be negative)")
+        formatter.write_str("a number of nanoseconds since 1970-01-01 (can be negative), a string containing one, or an RFC 3339 date string")
     }
 
     fn visit_i8<E: serde::de::Error>(self, v: i8) -> Result<Self::Value, E> {
@@ -318,10 +471,68 @@ impl<'de> Visitor<'de> for NanosecondTimestampVisitor {
             v.try_into().map_err(|e| E::custom(format!("u128 does not fit in i128: {e:?}")))?,
         ))
     }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        if !v.is_finite() || v.trunc() < i128::MIN as f64 || v.trunc() >= i128::MAX as f64 {
+            return Err(E::custom(format!("f64 is not a valid nanosecond timestamp: {v}")));
+        }
+        Ok(NsTimestamp(v.trunc() as i128))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if let Ok(nanos) = v.parse::<i128>() {
+            return Ok(NsTimestamp(nanos));
+        }
+        let date_time = DateTime::parse_from_rfc3339(v)
+            .map_err(|e| E::custom(format!("invalid timestamp {v:?}, expected a decimal integer or an RFC 3339 date: {e}")))?;
+        let nanos = date_time.timestamp() as i128 * 1_000_000_000i128 + date_time.timestamp_subsec_nanos() as i128;
+        Ok(NsTimestamp(nanos))
+    }
+
+    fn visit_borrowed_str<E: serde::de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        self.visit_str(v)
+    }
+}
+
+/// Name under which serde_json hands out the raw JSON text of a value, see [`serde_json::value::RawValue`].
+///
+/// serde_json reads integers that do not fit in 64 bits as [`f64`] when the type of the value is not known up front,
+/// so [`NsTimestamp`] asks for the raw text instead and parses integers itself.
+/// Other deserializers ignore this name and treat the timestamp as a regular newtype struct.
+const SERDE_JSON_RAW_VALUE_TOKEN: &str = "$serde_json::private::RawValue";
+
+struct RawOrNewtypeTimestampVisitor;
+
+impl<'de> Visitor<'de> for RawOrNewtypeTimestampVisitor {
+    type Value = NsTimestamp;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        NanosecondTimestampVisitor.expecting(formatter)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(NanosecondTimestampVisitor)
+        } else {
+            deserializer.deserialize_i128(NanosecondTimestampVisitor)
+        }
+    }
+
+    /// serde_json passes the raw JSON text as the value of a single-entry map.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (_, raw): (IgnoredAny, String) = map.next_entry()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        if let Ok(nanos) = raw.parse::<i128>() {
+            return Ok(NsTimestamp(nanos));
+        }
+        let mut deserializer = serde_json::Deserializer::from_str(&raw);
+        let timestamp = deserializer.deserialize_any(NanosecondTimestampVisitor).map_err(de::Error::custom)?;
+        deserializer.end().map_err(de::Error::custom)?;
+        Ok(timestamp)
+    }
 }
 
 impl<'de> Deserialize<'de> for NsTimestamp {
-    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        deserializer.deserialize_i128(NanosecondTimestampVisitor)
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(SERDE_JSON_RAW_VALUE_TOKEN, RawOrNewtypeTimestampVisitor)
     }
 }
@@ -13,9 +13,21 @@ use uuid::Uuid;
 /// You can convert between [`Uuid`] and [`UuidString`] easily by using `.into()`,
 /// or by reading the `.0` field of this struct,
 /// or by constructing this struct directly like this: `UuidString(uuid)`.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct UuidString(pub Uuid);
 
+impl UuidString {
+    /// Create a new random (version 4) UUID.
+    pub fn new_v4() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// Create a nil UUID (all zeros).
+    pub fn nil() -> Self {
+        Self(Uuid::nil())
+    }
+}
+
 impl From<Uuid> for UuidString {
     fn from(value: Uuid) -> Self {
         Self(value)
@@ -28,6 +40,21 @@ impl From<UuidString> for Uuid {
     }
 }
 
+impl FromStr for UuidString {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(Uuid::from_str(s)?))
+    }
+}
+
+impl Default for UuidString {
+    /// Create a nil UUID (all zeros).
+    fn default() -> Self {
+        Self::nil()
+    }
+}
+
 impl Serialize for UuidString {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         serializer.serialize_str(&self.0.to_string())
@@ -50,7 +77,8 @@ impl<'de> Visitor<'de> for UuidVisitor {
     }
 
     fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
-        Ok(UuidString(Uuid::from_str(v).unwrap()))
+        let uuid = Uuid::from_str(v).map_err(|e| E::custom(format!("invalid uuid {v:?}: {e}")))?;
+        Ok(UuidString(uuid))
     }
 
     fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {